@@ -1,20 +1,25 @@
 use super::errors::CliError;
-use chrono::{DateTime, Datelike, Duration, Local, TimeZone, Utc, Weekday};
+use crate::program::objects::{days_in_month, parse_time_of_day, Recurrence};
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
 
-pub const DATE_FMT: &str = "%F %T";
-
-fn calculate_day_difference(weekday: Weekday) -> i64 {
-    let current_day = Local::now().weekday().number_from_monday() as i64;
+fn calculate_day_difference(today: Weekday, weekday: Weekday) -> i64 {
+    let current_day = today.number_from_monday() as i64;
     let due_day = weekday.number_from_monday() as i64;
     (due_day - current_day + 7) % 7
 }
 
+#[deprecated(note = "use `parse_due_date`, which also understands times")]
+pub const DATE_FMT: &str = "%F %T";
+
+#[deprecated(note = "use `parse_due_date` with the weekday's name")]
 pub fn weekday_to_due_date(weekday: Weekday) -> DateTime<Utc> {
-    Local::today()
-        .checked_add_signed(Duration::days(calculate_day_difference(weekday)))
-        .unwrap()
-        .and_hms(8, 0, 0)
-        .with_timezone(&Utc)
+    let today = Local::today().naive_local();
+    let days = calculate_day_difference(today.weekday(), weekday);
+
+    local_to_utc((today + Duration::days(days)).and_hms(8, 0, 0))
 }
 
 pub fn string_to_weekday(string: &str) -> Result<Weekday, CliError> {
@@ -30,9 +35,374 @@ pub fn string_to_weekday(string: &str) -> Result<Weekday, CliError> {
     }
 }
 
+/// Parses a time of day such as `17:00`, `17:00:30`, `5pm` or `9:30am`.
+pub fn string_to_time(string: &str) -> Result<NaiveTime, CliError> {
+    parse_time_of_day(string).ok_or(CliError::ParsingError)
+}
+
+#[deprecated(note = "use `parse_due_date`, which also understands this form")]
 pub fn string_to_due_date(string: &str) -> Result<DateTime<Utc>, CliError> {
-    Ok(match Local.datetime_from_str(string, DATE_FMT) {
-        Ok(d) => d.with_timezone(&Utc),
-        Err(_) => return Err(CliError::ParsingError),
-    })
+    #[allow(deprecated)]
+    let naive =
+        NaiveDateTime::parse_from_str(string, DATE_FMT).map_err(|_| CliError::ParsingError)?;
+
+    Ok(local_to_utc(naive))
+}
+
+/// Parses a due date written the way people say it. The date can be `today`, `tomorrow`, a
+/// weekday, `next friday`, `in 3 days`, `+2w`, `next week`, `end of week` (`eow`),
+/// `end of month` (`eom`) or a date like `2026-11-03`, and may be followed by a time such as
+/// `17:00` or `5pm`. A time on its own means today. Dates without a time are due at
+/// `default_time`.
+pub fn parse_due_date(string: &str, default_time: NaiveTime) -> Result<DateTime<Utc>, CliError> {
+    let today = Local::today().naive_local();
+
+    Ok(local_to_utc(resolve_due_date(string, today, default_time)?))
+}
+
+fn resolve_due_date(
+    string: &str,
+    today: NaiveDate,
+    default_time: NaiveTime,
+) -> Result<NaiveDateTime, CliError> {
+    let lowered = string.trim().to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+
+    let (date_words, time) = match words.split_last() {
+        Some((last, rest)) => match string_to_time(last) {
+            Ok(t) => (rest, t),
+            Err(_) => (&words[..], default_time),
+        },
+        None => return Err(CliError::ParsingError),
+    };
+
+    let date = if date_words.is_empty() {
+        today
+    } else {
+        phrase_to_date(&date_words.join(" "), today)?
+    };
+
+    Ok(date.and_time(time))
+}
+
+fn phrase_to_date(phrase: &str, today: NaiveDate) -> Result<NaiveDate, CliError> {
+    match phrase {
+        "today" | "tod" => return Ok(today),
+        "tomorrow" | "tom" | "tmr" => return Ok(today + Duration::days(1)),
+        "next week" => return Ok(today + Duration::weeks(1)),
+        "next month" => return add_months(today, 1).ok_or(CliError::ParsingError),
+        "end of week" | "eow" => {
+            return Ok(
+                today + Duration::days(calculate_day_difference(today.weekday(), Weekday::Sun))
+            )
+        }
+        "end of month" | "eom" => {
+            return Ok(NaiveDate::from_ymd(
+                today.year(),
+                today.month(),
+                days_in_month(today.year(), today.month()),
+            ))
+        }
+        _ => (),
+    }
+
+    if let Ok(weekday) = string_to_weekday(phrase) {
+        return Ok(today + Duration::days(calculate_day_difference(today.weekday(), weekday)));
+    }
+
+    // "next friday" is never today
+    if let Some(day) = phrase.strip_prefix("next ") {
+        let weekday = string_to_weekday(day)?;
+        let difference = match calculate_day_difference(today.weekday(), weekday) {
+            0 => 7,
+            d => d,
+        };
+        return Ok(today + Duration::days(difference));
+    }
+
+    if let Some(offset) = phrase.strip_prefix("in ") {
+        let mut parts = offset.split_whitespace();
+        let amount = parts.next().unwrap_or("");
+        let unit = parts.next().unwrap_or("");
+        if parts.next().is_some() {
+            return Err(CliError::ParsingError);
+        }
+        return add_offset(today, amount, unit);
+    }
+
+    if let Some(offset) = phrase.strip_prefix('+') {
+        let unit_start = offset
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(offset.len());
+        return add_offset(today, &offset[..unit_start], offset[unit_start..].trim());
+    }
+
+    NaiveDate::parse_from_str(phrase, "%Y-%m-%d").map_err(|_| CliError::ParsingError)
+}
+
+fn add_offset(today: NaiveDate, amount: &str, unit: &str) -> Result<NaiveDate, CliError> {
+    let amount: u32 = amount.parse().map_err(|_| CliError::ParsingError)?;
+
+    let date = match unit {
+        "d" | "day" | "days" => today.checked_add_signed(Duration::days(i64::from(amount))),
+        "w" | "week" | "weeks" => today.checked_add_signed(Duration::weeks(i64::from(amount))),
+        "m" | "month" | "months" => add_months(today, amount),
+        "y" | "year" | "years" => amount.checked_mul(12).and_then(|m| add_months(today, m)),
+        _ => return Err(CliError::ParsingError),
+    };
+
+    // Past the last date chrono can represent
+    date.ok_or(CliError::ParsingError)
+}
+
+/// Parses when a period that has already begun started, for reports. The date can be `today`,
+/// `yesterday`, a weekday (the latest one, which may be today), `last week`, `3 days ago`,
+/// `-2w`, `start of week` (`sow`), `start of month` (`som`) or a date like `2026-10-01`, and may
+/// be followed by a time. Dates without a time start at midnight.
+pub fn parse_since(string: &str) -> Result<DateTime<Utc>, CliError> {
+    let today = Local::today().naive_local();
+
+    Ok(local_to_utc(resolve_since(string, today)?))
+}
+
+fn resolve_since(string: &str, today: NaiveDate) -> Result<NaiveDateTime, CliError> {
+    let lowered = string.trim().to_lowercase();
+    let words: Vec<&str> = lowered.split_whitespace().collect();
+
+    let (date_words, time) = match words.split_last() {
+        Some((last, rest)) => match string_to_time(last) {
+            Ok(t) => (rest, t),
+            Err(_) => (&words[..], NaiveTime::from_hms(0, 0, 0)),
+        },
+        None => return Err(CliError::ParsingError),
+    };
+
+    let date = if date_words.is_empty() {
+        today
+    } else {
+        phrase_to_past_date(&date_words.join(" "), today)?
+    };
+
+    Ok(date.and_time(time))
+}
+
+fn phrase_to_past_date(phrase: &str, today: NaiveDate) -> Result<NaiveDate, CliError> {
+    match phrase {
+        "today" | "tod" => return Ok(today),
+        "yesterday" | "yest" => return Ok(today - Duration::days(1)),
+        "last week" => return Ok(today - Duration::weeks(1)),
+        "start of week" | "sow" => {
+            return Ok(today - Duration::days(today.weekday().num_days_from_monday() as i64))
+        }
+        "start of month" | "som" => return Ok(today.with_day(1).unwrap()),
+        _ => (),
+    }
+
+    if let Ok(weekday) = string_to_weekday(phrase) {
+        return Ok(today - Duration::days(calculate_day_difference(weekday, today.weekday())));
+    }
+
+    if let Some(offset) = phrase.strip_suffix(" ago") {
+        let mut parts = offset.split_whitespace();
+        let amount = parts.next().unwrap_or("");
+        let unit = parts.next().unwrap_or("");
+        if parts.next().is_some() {
+            return Err(CliError::ParsingError);
+        }
+        return subtract_offset(today, amount, unit);
+    }
+
+    if let Some(offset) = phrase.strip_prefix('-') {
+        let unit_start = offset
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(offset.len());
+        return subtract_offset(today, &offset[..unit_start], offset[unit_start..].trim());
+    }
+
+    NaiveDate::parse_from_str(phrase, "%Y-%m-%d").map_err(|_| CliError::ParsingError)
+}
+
+fn subtract_offset(today: NaiveDate, amount: &str, unit: &str) -> Result<NaiveDate, CliError> {
+    let amount: u32 = amount.parse().map_err(|_| CliError::ParsingError)?;
+
+    let date = match unit {
+        "d" | "day" | "days" => today.checked_sub_signed(Duration::days(i64::from(amount))),
+        "w" | "week" | "weeks" => today.checked_sub_signed(Duration::weeks(i64::from(amount))),
+        _ => return Err(CliError::ParsingError),
+    };
+
+    // Before the first date chrono can represent
+    date.ok_or(CliError::ParsingError)
+}
+
+/// Moves a date forward by whole months, keeping the day of the month where possible and
+/// falling back to the last day of shorter months. Returns `None` past the last representable
+/// date.
+fn add_months(date: NaiveDate, months: u32) -> Option<NaiveDate> {
+    let month0 = date.month0().checked_add(months)?;
+    let year = date.year().checked_add((month0 / 12) as i32)?;
+    let month = month0 % 12 + 1;
+
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+/// Times skipped by a daylight saving change are moved an hour later.
+fn local_to_utc(local: NaiveDateTime) -> DateTime<Utc> {
+    match Local.from_local_datetime(&local).earliest() {
+        Some(d) => d.with_timezone(&Utc),
+        None => Local
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc),
+    }
+}
+
+/// Parses a recurrence such as `daily`, `weekly`, `weekly:mon,fri`, `monthly:15` or `every:3`.
+pub fn string_to_recurrence(string: &str) -> Result<Recurrence, CliError> {
+    let lowered = string.trim().to_lowercase();
+    let mut parts = lowered.splitn(2, ':');
+    let kind = parts.next().unwrap_or("");
+    let argument = parts.next();
+
+    let in_range = |n: u32| {
+        Local::today()
+            .naive_local()
+            .checked_add_signed(Duration::days(i64::from(n)))
+            .is_some()
+    };
+
+    match (kind, argument) {
+        ("daily", None) => Ok(Recurrence::Daily),
+        ("weekly", None) => Ok(Recurrence::Weekly(Vec::new())),
+        ("weekly", Some(days)) => {
+            let mut weekdays = Vec::new();
+            for day in days.split(',') {
+                let weekday = string_to_weekday(day.trim())?;
+                if !weekdays.contains(&weekday) {
+                    weekdays.push(weekday);
+                }
+            }
+            Ok(Recurrence::Weekly(weekdays))
+        }
+        ("monthly", Some(day)) => match day.parse::<u32>() {
+            Ok(d) if (1..=31).contains(&d) => Ok(Recurrence::Monthly(d)),
+            _ => Err(CliError::ParsingError),
+        },
+        ("every", Some(days)) => match days.trim_end_matches('d').parse::<u32>() {
+            // The next occurrence has to fall on a date that can be represented
+            Ok(n) if n >= 1 && in_range(n) => Ok(Recurrence::EveryNDays(n)),
+            _ => Err(CliError::ParsingError),
+        },
+        _ => Err(CliError::ParsingError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(string: &str) -> NaiveDateTime {
+        // A Wednesday
+        let today = NaiveDate::from_ymd(2026, 10, 14);
+        resolve_due_date(string, today, NaiveTime::from_hms(8, 0, 0)).unwrap()
+    }
+
+    #[test]
+    fn natural_due_dates() {
+        let at = |y, m, d, h, min| NaiveDate::from_ymd(y, m, d).and_hms(h, min, 0);
+
+        assert_eq!(resolve("today"), at(2026, 10, 14, 8, 0));
+        assert_eq!(resolve("tomorrow 17:00"), at(2026, 10, 15, 17, 0));
+        assert_eq!(resolve("in 3 days"), at(2026, 10, 17, 8, 0));
+        assert_eq!(resolve("friday"), at(2026, 10, 16, 8, 0));
+        assert_eq!(resolve("next wed 5pm"), at(2026, 10, 21, 17, 0));
+        assert_eq!(resolve("eow"), at(2026, 10, 18, 8, 0));
+        assert_eq!(resolve("End of Month"), at(2026, 10, 31, 8, 0));
+        assert_eq!(resolve("+2w"), at(2026, 10, 28, 8, 0));
+        assert_eq!(resolve("+4m"), at(2027, 2, 14, 8, 0));
+        assert_eq!(resolve("2026-11-03"), at(2026, 11, 3, 8, 0));
+        assert_eq!(resolve("2026-11-03 09:30:00"), at(2026, 11, 3, 9, 30));
+        assert_eq!(resolve("12am"), at(2026, 10, 14, 0, 0));
+
+        for bad in &[
+            "",
+            "someday",
+            "in 3",
+            "next",
+            "+2x",
+            "13pm",
+            "in 4000000000 days",
+            "+4000000000w",
+            "+999999999y",
+            "in 4294967295 months",
+        ] {
+            let today = NaiveDate::from_ymd(2026, 10, 14);
+            assert!(resolve_due_date(bad, today, NaiveTime::from_hms(8, 0, 0)).is_err());
+        }
+    }
+
+    #[test]
+    fn past_dates() {
+        let today = NaiveDate::from_ymd(2026, 10, 14);
+        let since = |string| resolve_since(string, today).unwrap();
+        let at = |m, d, h| NaiveDate::from_ymd(2026, m, d).and_hms(h, 0, 0);
+
+        assert_eq!(since("monday"), at(10, 12, 0));
+        assert_eq!(since("wed"), at(10, 14, 0));
+        assert_eq!(since("thursday 9:00"), at(10, 8, 9));
+        assert_eq!(since("yesterday"), at(10, 13, 0));
+        assert_eq!(since("3 days ago"), at(10, 11, 0));
+        assert_eq!(since("-2w"), at(9, 30, 0));
+        assert_eq!(since("sow"), at(10, 12, 0));
+        assert_eq!(since("start of month"), at(10, 1, 0));
+        assert_eq!(since("2026-09-01"), at(9, 1, 0));
+        for bad in &["next friday", "99999999999999 days ago", "-4000000000w"] {
+            assert!(resolve_since(bad, today).is_err());
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_helpers_agree() {
+        let eight = NaiveTime::from_hms(8, 0, 0);
+        assert_eq!(
+            string_to_due_date("2026-11-03 17:00:00").unwrap(),
+            parse_due_date("2026-11-03 17:00", eight).unwrap()
+        );
+        assert!(string_to_due_date("friday").is_err());
+        assert_eq!(
+            weekday_to_due_date(Weekday::Fri),
+            parse_due_date("friday", eight).unwrap()
+        );
+    }
+
+    #[test]
+    fn months_are_clamped() {
+        assert_eq!(
+            add_months(NaiveDate::from_ymd(2026, 1, 31), 1),
+            Some(NaiveDate::from_ymd(2026, 2, 28))
+        );
+        assert_eq!(
+            add_months(NaiveDate::from_ymd(2026, 1, 31), 4_000_000_000),
+            None
+        );
+    }
+
+    #[test]
+    fn recurrences() {
+        assert_eq!(
+            string_to_recurrence("every:4d").ok(),
+            Some(Recurrence::EveryNDays(4))
+        );
+        assert_eq!(
+            string_to_recurrence("monthly:31").ok(),
+            Some(Recurrence::Monthly(31))
+        );
+        for bad in &["every:0", "every:4000000000", "monthly:32", "yearly"] {
+            assert!(string_to_recurrence(bad).is_err());
+        }
+    }
 }
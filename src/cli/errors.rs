@@ -3,3 +3,14 @@ pub enum CliError {
     ParsingError,
     Other,
 }
+
+impl std::error::Error for CliError {}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CliError::ParsingError => write!(f, "Could not understand the value given."),
+            CliError::Other => write!(f, "An error occurred."),
+        }
+    }
+}
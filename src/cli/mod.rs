@@ -1,65 +1,208 @@
 use crate::program;
+use crate::program::errors::EfficacyError;
+use crate::program::interchange;
+use crate::program::objects::{Priority, RecurFrom, RecurrenceRule, Task, TaskEdit};
+use crate::program::query::{Query, Selection};
+use chrono::NaiveTime;
 use clap::App;
 use std::error::Error;
+use std::io::Read;
 
 pub mod dates;
-mod errors;
+pub mod errors;
+
+const DUE_DATE_HELP: &str = "Due date provided isn't understood. Try something like 'tomorrow 17:00', 'friday', 'next mon', 'in 3 days', '+2w', 'eow', 'eom' or 'YYYY-MM-DD HH:MM'.";
+
+const SINCE_HELP: &str = "Start of the report isn't understood. Try something like 'today', 'monday', 'yesterday 9:00', '3 days ago', '-2w', 'sow', 'som' or 'YYYY-MM-DD'.";
 
 pub fn parse() -> Result<(), Box<dyn Error>> {
-    let settings = program::settings::Settings::new()?;
-    let mut eff = program::Efficacy::init(&settings)?;
+    let mut settings = program::settings::Settings::new()?;
+    let default_due_time = settings.default_due_time;
 
     let yaml = load_yaml!("cli.yml");
     let matches = App::from_yaml(yaml).get_matches();
 
+    if let Some(output) = matches.value_of("output") {
+        settings.output = String::from(output);
+    }
+
+    // Recovery has to work when the data can't be loaded
+    if matches.subcommand_matches("recover").is_some() {
+        match program::Efficacy::recover(&settings) {
+            Ok(restored) if restored.is_empty() => println!("All data files are readable."),
+            Ok(restored) => {
+                for path in restored {
+                    println!("Restored {} from its backup.", path.display());
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+        return Ok(());
+    }
+
+    // Only read-only commands can run alongside another command
+    let init_result = match matches.subcommand_name() {
+        Some("list") | Some("export") | Some("report") | Some("debug") | None => {
+            program::Efficacy::init(&settings)
+        }
+        _ => program::Efficacy::init_locked(&settings),
+    };
+    let mut eff = match init_result {
+        Ok(eff) => eff,
+        Err(e @ EfficacyError::LockTimeoutError) | Err(e @ EfficacyError::CorruptFileError { .. }) => {
+            println!("{}", e);
+            return Ok(());
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
+
     // LS command
     if let Some(matches) = matches.subcommand_matches("list") {
         if matches.is_present("context") {
             println!("{}", eff.list_contexts()?);
         } else {
-            match matches.value_of("ID") {
-                Some(id) => {
-                    let id: usize = id.parse()?;
+            let query = values_of_strings(matches, "QUERY").join(" ");
+            let archived = matches.is_present("archived");
+            match query.parse::<usize>() {
+                Ok(id) if !archived => {
                     let task_string = eff
                         .list_task(id)
                         .unwrap_or(String::from("Invalid ID provided"));
                     println!("{}", task_string);
                 }
-                None => println!("{}", eff.list()?),
+                _ => {
+                    let tags = values_of_strings(matches, "tag");
+                    let without_tags = values_of_strings(matches, "without-tag");
+                    let filter = match Query::parse(&query, |d| {
+                        dates::parse_due_date(d, default_due_time).ok()
+                    }) {
+                        Ok(q) => q,
+                        Err(e) => {
+                            println!("{}", e);
+                            return Ok(());
+                        }
+                    };
+                    let sort = matches
+                        .value_of("sort")
+                        .unwrap_or(&settings.sort)
+                        .parse()
+                        .unwrap_or_default();
+                    let group_by = matches
+                        .value_of("group-by")
+                        .unwrap_or(&settings.group_by)
+                        .parse()
+                        .unwrap_or_default();
+                    let filter = filter.and(Query::tagged(&tags, &without_tags));
+                    if archived {
+                        println!("{}", eff.list_archived(&filter, sort, group_by)?);
+                    } else {
+                        println!("{}", eff.list_matching(&filter, sort, group_by)?);
+                    }
+                }
             }
         }
 
     // DONE command
     } else if let Some(matches) = matches.subcommand_matches("done") {
-        let id = value_t_or_exit!(matches.value_of("ID"), usize);
-        eff.complete_task(id)?;
-        println!("{}", eff.list()?);
+        let ids = match selected_tasks(&eff, matches, default_due_time)? {
+            Some(ids) => ids,
+            None => return Ok(()),
+        };
+        let with_subtasks = matches.is_present("subtasks");
+
+        // Tasks completed together don't block each other
+        let mut blocked = false;
+        for &id in &ids {
+            let open_blockers: Vec<usize> = eff
+                .open_blockers(id)
+                .into_iter()
+                .filter(|b| !ids.contains(b))
+                .collect();
+            if !open_blockers.is_empty() && !matches.is_present("force") {
+                println!(
+                    "Task #{} is blocked by {}. Use --force to complete it anyway.",
+                    id,
+                    format_ids(&open_blockers)
+                );
+                blocked = true;
+            }
+        }
+        if blocked {
+            return Ok(());
+        }
+
+        let open_subtasks: Vec<(usize, Vec<usize>)> = ids
+            .iter()
+            .map(|&id| {
+                let open = eff.open_subtasks(id);
+                (id, open.into_iter().filter(|s| !ids.contains(s)).collect())
+            })
+            .collect();
+        match eff.complete_tasks(&ids, with_subtasks) {
+            Ok(_) => println!("{}", eff.list()?),
+            Err(e @ EfficacyError::RecurrenceError(_)) => {
+                println!("{}", e);
+                return Ok(());
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+        if !with_subtasks {
+            for (id, open) in open_subtasks.iter().filter(|(_, open)| !open.is_empty()) {
+                println!(
+                    "Warning: task #{} still has {} open subtask(s). Use --subtasks to complete them too.",
+                    id,
+                    open.len()
+                );
+            }
+        }
 
     // ADD command
     } else if let Some(matches) = matches.subcommand_matches("add") {
-        let category = match matches.value_of("category") {
-            Some(s) => Option::Some(String::from(s)),
-            None => Option::None,
-        };
-        let information = match matches.value_of("information") {
-            Some(s) => Some(String::from(s)),
+        let category = matches.value_of("category").map(String::from);
+        let information = matches.value_of("information").map(String::from);
+        let due = match matches.value_of("due") {
+            Some(d) => match dates::parse_due_date(d, default_due_time) {
+                Ok(dd) => Some(dd),
+                Err(_) => {
+                    println!("{}", DUE_DATE_HELP);
+                    return Ok(());
+                }
+            },
             None => None,
         };
-        let due = match matches.value_of("due") {
-            Some(d) => match dates::string_to_weekday(d) {
-                Ok(w) => Some(dates::weekday_to_due_date(w)),
-                Err(_) => match dates::string_to_due_date(d) {
-                    Ok(dd) => Some(dd),
-                    Err(_) => {
-                        println!("Due date provided isn't in 'YYYY-MM-DD HH:MM:SS' format.");
-                        return Ok(());
-                    }
-                },
+        let recurrence = match matches.value_of("repeat") {
+            Some(r) => match dates::string_to_recurrence(r) {
+                Ok(every) => Some(RecurrenceRule {
+                    every,
+                    from: matches
+                        .value_of("repeat-from")
+                        .map(string_to_recur_from)
+                        .unwrap_or_default(),
+                }),
+                Err(_) => {
+                    println!("Recurrence provided isn't one of 'daily', 'weekly', 'weekly:mon,fri', 'monthly:15' or 'every:3'.");
+                    return Ok(());
+                }
             },
             None => None,
         };
         let description = value_t_or_exit!(matches.value_of("DESCRIPTION"), String);
-        match eff.add_task(description, category, information, due) {
+        let new_task = Task {
+            category,
+            information,
+            due,
+            priority: value_t!(matches, "priority", Priority).unwrap_or_default(),
+            tags: values_of_strings(matches, "tag").into_iter().collect(),
+            recurrence,
+            parent: if matches.is_present("parent") {
+                Some(value_t_or_exit!(matches, "parent", usize))
+            } else {
+                None
+            },
+            depends_on: values_of_ids(matches, "blocked-by").into_iter().collect(),
+            ..Task::new(description)
+        };
+        match eff.add_task(new_task) {
             Ok(_) => println!("{}", eff.list()?),
             Err(_) => println!("There was an error in creating the new task."),
         }
@@ -67,37 +210,57 @@ pub fn parse() -> Result<(), Box<dyn Error>> {
     // EDIT command
     } else if let Some(matches) = matches.subcommand_matches("edit") {
         if let Some(matches) = matches.subcommand_matches("task") {
-            let id = value_t_or_exit!(matches.value_of("ID"), usize);
-            let description = match matches.value_of("description") {
-                Some(s) => Some(String::from(s)),
-                None => None,
+            let mut edit = TaskEdit {
+                description: matches.value_of("description").map(String::from),
+                category: matches.value_of("category").map(String::from),
+                information: matches.value_of("information").map(String::from),
+                priority: value_t!(matches, "priority", Priority).ok(),
+                add_tags: values_of_strings(matches, "tag"),
+                remove_tags: values_of_strings(matches, "untag"),
+                recur_from: matches.value_of("repeat-from").map(string_to_recur_from),
+                add_dependencies: values_of_ids(matches, "blocked-by"),
+                remove_dependencies: values_of_ids(matches, "unblock"),
+                ..TaskEdit::default()
             };
-            let category = match matches.value_of("category") {
-                Some(s) => Some(String::from(s)),
+            edit.recurrence = match matches.value_of("repeat") {
+                Some("none") => Some(None),
+                Some(r) => match dates::string_to_recurrence(r) {
+                    Ok(every) => Some(Some(every)),
+                    Err(_) => {
+                        println!("Recurrence provided isn't one of 'daily', 'weekly', 'weekly:mon,fri', 'monthly:15', 'every:3' or 'none'.");
+                        return Ok(());
+                    }
+                },
                 None => None,
             };
-            let information = match matches.value_of("information") {
-                Some(s) => Some(String::from(s)),
+            edit.due = match matches.value_of("due") {
+                Some(d) => match dates::parse_due_date(d, default_due_time) {
+                    Ok(dd) => Some(dd),
+                    Err(_) => {
+                        println!("{}", DUE_DATE_HELP);
+                        return Ok(());
+                    }
+                },
                 None => None,
             };
-            let due = match matches.value_of("due") {
-                Some(d) => match dates::string_to_weekday(d) {
-                    Ok(w) => Some(dates::weekday_to_due_date(w)),
-                    Err(_) => match dates::string_to_due_date(d) {
-                        Ok(dd) => Some(dd),
-                        Err(_) => {
-                            println!("Due date provided isn't in 'YYYY-MM-DD HH:MM:SS' format.");
-                            return Ok(());
-                        }
-                    },
-                },
+            edit.parent = match matches.value_of("parent") {
+                Some("none") => Some(None),
+                Some(_) => Some(Some(value_t_or_exit!(matches, "parent", usize))),
                 None => None,
             };
 
-            if description.is_some() || category.is_some() || information.is_some() || due.is_some()
-            {
-                eff.edit_task(id, description, category, information, due)?;
-                println!("{}", eff.list()?);
+            if !edit.is_empty() {
+                let ids = match selected_tasks(&eff, matches, default_due_time)? {
+                    Some(ids) => ids,
+                    None => return Ok(()),
+                };
+                match eff.edit_tasks(&ids, edit) {
+                    Ok(_) => println!("{}", eff.list()?),
+                    Err(EfficacyError::MismatchedIdError) => println!("Invalid ID provided."),
+                    Err(e @ EfficacyError::CyclicParentError)
+                    | Err(e @ EfficacyError::CyclicDependencyError) => println!("{}", e),
+                    Err(e) => return Err(Box::new(e)),
+                }
             } else {
                 println!("No new information provided.");
             }
@@ -112,16 +275,16 @@ pub fn parse() -> Result<(), Box<dyn Error>> {
     // DELETE commands
     } else if let Some(matches) = matches.subcommand_matches("delete") {
         if let Some(matches) = matches.subcommand_matches("task") {
-            let id = value_t_or_exit!(matches.value_of("ID"), usize);
-            match eff.delete_task(id) {
+            let ids = match selected_tasks(&eff, matches, default_due_time)? {
+                Some(ids) => ids,
+                None => return Ok(()),
+            };
+            match eff.delete_tasks(&ids, matches.is_present("keep-subtasks")) {
                 Ok(_) => println!("{}", eff.list()?),
                 Err(_) => println!("There was an error in deleting the task"),
             }
         } else if let Some(matches) = matches.subcommand_matches("category") {
-            let title = match matches.value_of("TITLE") {
-                Some(s) => Option::Some(String::from(s)),
-                None => Option::None,
-            };
+            let title = matches.value_of("TITLE").map(String::from);
 
             match eff.delete_category(title) {
                 Ok(_) => println!("{}", eff.list()?),
@@ -135,11 +298,88 @@ pub fn parse() -> Result<(), Box<dyn Error>> {
             }
         }
 
+    // TRASH commands
+    } else if let Some(matches) = matches.subcommand_matches("trash") {
+        if let Some(matches) = matches.subcommand_matches("restore") {
+            let id = value_t_or_exit!(matches.value_of("ID"), usize);
+            match eff.restore_from_trash(id) {
+                Ok(restored) => {
+                    println!("{}", eff.list()?);
+                    println!("Restored #{} with {} tasks.", id, restored);
+                }
+                Err(EfficacyError::MismatchedIdError) => {
+                    println!("There is nothing in the trash with ID #{}.", id)
+                }
+                Err(e @ EfficacyError::TrashError(_)) => println!("{}", e),
+                Err(e) => return Err(Box::new(e)),
+            }
+        } else if matches.subcommand_matches("empty").is_some() {
+            let count = eff.empty_trash()?;
+            println!("Deleted {} items from the trash for good.", count);
+        } else {
+            println!("{}", eff.list_trash()?);
+        }
+
     // CLEAN command
-    } else if let Some(_) = matches.subcommand_matches("clean") {
+    } else if matches.subcommand_matches("clean").is_some() {
         eff.clean()?;
         println!("{}", eff.list()?);
 
+    // RESTORE command
+    } else if let Some(matches) = matches.subcommand_matches("restore") {
+        let id = value_t_or_exit!(matches.value_of("ID"), usize);
+        match eff.restore(id) {
+            Ok(_) => println!("{}", eff.list()?),
+            Err(EfficacyError::MismatchedIdError) => {
+                println!("There is no archived task #{}.", id)
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+
+    // START and STOP commands
+    } else if let Some(matches) = matches.subcommand_matches("start") {
+        let id = value_t_or_exit!(matches.value_of("ID"), usize);
+        match eff.start_task(id) {
+            Ok(stopped) => {
+                println!("{}", eff.list()?);
+                if let Some(stopped) = stopped {
+                    println!("{}", stopped_message(&eff, stopped));
+                }
+                println!("Started #{}.", id);
+            }
+            Err(EfficacyError::MismatchedIdError) => println!("Invalid ID provided."),
+            Err(e @ EfficacyError::CompletedTaskError(_)) => println!("{}", e),
+            Err(e) => return Err(Box::new(e)),
+        }
+    } else if matches.subcommand_matches("stop").is_some() {
+        match eff.stop_task()? {
+            Some(stopped) => {
+                println!("{}", eff.list()?);
+                println!("{}", stopped_message(&eff, stopped));
+            }
+            None => println!("No task is being recorded."),
+        }
+
+    // REPORT commands
+    } else if let Some(matches) = matches.subcommand_matches("report") {
+        if let Some(matches) = matches.subcommand_matches("time") {
+            let since = match matches.value_of("since") {
+                Some(s) => match dates::parse_since(s) {
+                    Ok(since) => Some(since),
+                    Err(_) => {
+                        println!("{}", SINCE_HELP);
+                        return Ok(());
+                    }
+                },
+                None => None,
+            };
+            if matches.is_present("csv") {
+                print!("{}", eff.time_report_csv(since)?);
+            } else {
+                println!("{}", eff.time_report(since)?);
+            }
+        }
+
     // CONTEXT command
     } else if let Some(matches) = matches.subcommand_matches("context") {
         let context = match matches.value_of("CONTEXT") {
@@ -166,27 +406,155 @@ pub fn parse() -> Result<(), Box<dyn Error>> {
 
         println!("{}", eff.list()?);
 
+    // UNDO command
+    } else if matches.subcommand_matches("undo").is_some() {
+        match eff.undo()? {
+            Some(operation) => {
+                println!("{}", eff.list()?);
+                println!("Undid '{}'.", operation);
+            }
+            None => println!("Nothing to undo."),
+        }
+
+    // REDO command
+    } else if matches.subcommand_matches("redo").is_some() {
+        match eff.redo()? {
+            Some(operation) => {
+                println!("{}", eff.list()?);
+                println!("Redid '{}'.", operation);
+            }
+            None => println!("Nothing to redo."),
+        }
+
+    // EXPORT command
+    } else if let Some(matches) = matches.subcommand_matches("export") {
+        let format = value_t_or_exit!(matches.value_of("format"), interchange::Format);
+        match eff.export(format) {
+            Ok(exported) => print!("{}", exported),
+            Err(e @ EfficacyError::ImportOnlyFormatError(_)) => println!("{}", e),
+            Err(e) => return Err(Box::new(e)),
+        }
+
+    // IMPORT command
+    } else if let Some(matches) = matches.subcommand_matches("import") {
+        let format = value_t_or_exit!(matches.value_of("format"), interchange::Format);
+        let contents = match matches.value_of("FILE") {
+            Some("-") | None => {
+                let mut contents = String::new();
+                std::io::stdin().read_to_string(&mut contents)?;
+                contents
+            }
+            Some(path) => std::fs::read_to_string(path)?,
+        };
+        match eff.import(format, &contents) {
+            Ok(ids) => {
+                println!("{}", eff.list()?);
+                println!("Imported {} task(s).", ids.len());
+            }
+            Err(e @ EfficacyError::ImportError { .. })
+            | Err(e @ EfficacyError::ExportOnlyFormatError(_))
+            | Err(e @ EfficacyError::CyclicParentError)
+            | Err(e @ EfficacyError::CyclicDependencyError) => println!("{}", e),
+            Err(e) => return Err(Box::new(e)),
+        }
+
     // DEBUG command
-    } else if let Some(_) = matches.subcommand_matches("debug") {
+    } else if matches.subcommand_matches("debug").is_some() {
         eff.debug()?;
     }
 
     Ok(())
 }
 
-// mod test {
-//     #[test]
-//     fn test_due_date() {
-//         let test_str = "2020-01-14 09:10:00";
-//         let ndt = NaiveDateTime::parse_from_str(test_str, dates::DATE_FMT);
-//         println!("Naive: {:?}", ndt);
-
-//         let adt = Local.datetime_from_str(test_str, dates::DATE_FMT);
-
-//         // let adt = DateTime::parse_from_str(test_str, dates::DATE_FMT);
-//         println!("DateTime: {:?}", adt);
-//         // let adt = adt.unwrap().with_timezone(&Local);
-//         // println!("With timezone: {:?}", adt);
-//         // println!("days to add: {}", days_to_add);
-//     }
-// }
+fn string_to_recur_from(value: &str) -> RecurFrom {
+    match value {
+        "done" => RecurFrom::Completion,
+        _ => RecurFrom::Due,
+    }
+}
+
+fn values_of_ids(matches: &clap::ArgMatches, name: &str) -> Vec<usize> {
+    if matches.is_present(name) {
+        values_t_or_exit!(matches, name, usize)
+    } else {
+        Vec::new()
+    }
+}
+
+/// IDs of the tasks picked by the `TASKS` argument, or `None` once it has been explained why
+/// there aren't any.
+fn selected_tasks(
+    eff: &program::Efficacy,
+    matches: &clap::ArgMatches,
+    default_due_time: NaiveTime,
+) -> Result<Option<Vec<usize>>, Box<dyn Error>> {
+    let words = values_of_strings(matches, "TASKS");
+    let parse_date = |d: &str| dates::parse_due_date(d, default_due_time).ok();
+    let selection = match Selection::parse(&words, parse_date) {
+        Ok(s) => s,
+        Err(e) => {
+            println!("{}", e);
+            return Ok(None);
+        }
+    };
+
+    match eff.select(&selection) {
+        Ok(ids) if ids.is_empty() => {
+            println!("No tasks match the filter.");
+            Ok(None)
+        }
+        Ok(ids) => Ok(Some(ids)),
+        Err(EfficacyError::MismatchedIdError) => {
+            println!("Invalid ID provided.");
+            Ok(None)
+        }
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+fn format_ids(ids: &[usize]) -> String {
+    ids.iter()
+        .map(|id| format!("#{}", id))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Names the context of a stopped task when it isn't the current one.
+fn stopped_message(eff: &program::Efficacy, (context_name, id): (String, usize)) -> String {
+    if context_name == eff.current_context() {
+        format!("Stopped #{}.", id)
+    } else {
+        format!("Stopped #{} in context '{}'.", id, context_name)
+    }
+}
+
+fn values_of_strings(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    match matches.values_of(name) {
+        Some(values) => values.map(String::from).collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn priority_option() {
+        let yaml = load_yaml!("cli.yml");
+        let parse = |args: &[&str]| App::from_yaml(yaml).get_matches_from_safe(args);
+
+        let matches = parse(&["efficacy", "add", "Write report", "-p", "high"]).unwrap();
+        let add = matches.subcommand_matches("add").unwrap();
+        assert_eq!(value_t!(add, "priority", Priority).unwrap(), Priority::High);
+
+        let matches = parse(&["efficacy", "edit", "task", "3", "-p", "low"]).unwrap();
+        let task = matches
+            .subcommand_matches("edit")
+            .and_then(|m| m.subcommand_matches("task"))
+            .unwrap();
+        assert_eq!(value_t!(task, "priority", Priority).unwrap(), Priority::Low);
+
+        assert!(parse(&["efficacy", "add", "Write report", "-p", "urgent"]).is_err());
+    }
+}
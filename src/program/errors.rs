@@ -3,10 +3,33 @@ pub enum EfficacyError {
     ConfigError(config::ConfigError),
     IOError(std::io::Error),
     SerdeJsonError(serde_json::Error),
+    SqliteError(rusqlite::Error),
     MismatchedIdError,
+    CyclicParentError,
+    CyclicDependencyError,
     NonexistentCategoryError,
     MalformedContextError,
     BadContextNameError,
+    LockTimeoutError,
+    FileChangedError(std::path::PathBuf),
+    UnsupportedVersionError(u64),
+    CorruptFileError {
+        path: std::path::PathBuf,
+        line: usize,
+        column: usize,
+        quarantine_path: Option<std::path::PathBuf>,
+    },
+    NoBackupError(std::path::PathBuf),
+    QueryError(String),
+    ImportError {
+        line: usize,
+        message: String,
+    },
+    ExportOnlyFormatError(String),
+    ImportOnlyFormatError(String),
+    TrashError(String),
+    CompletedTaskError(usize),
+    RecurrenceError(usize),
     Other,
 }
 
@@ -14,7 +37,74 @@ impl std::error::Error for EfficacyError {}
 
 impl std::fmt::Display for EfficacyError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "An error occurred:")
+        match self {
+            EfficacyError::LockTimeoutError => write!(
+                f,
+                "Another efficacy command is still running. Try again once it has finished."
+            ),
+            EfficacyError::FileChangedError(path) => write!(
+                f,
+                "{} was changed by something else since it was loaded. Refusing to overwrite it, run the command again.",
+                path.display()
+            ),
+            EfficacyError::UnsupportedVersionError(version) => write!(
+                f,
+                "The data file is version {}, which is newer than this version of efficacy understands. Please upgrade efficacy.",
+                version
+            ),
+            EfficacyError::CorruptFileError {
+                path,
+                line,
+                column,
+                quarantine_path,
+            } => {
+                write!(f, "Could not read {}", path.display())?;
+                // Errors that aren't about the syntax have no position
+                if *line > 0 {
+                    write!(f, " (line {}, column {})", line, column)?;
+                }
+                write!(f, ". Nothing has been changed.")?;
+                if let Some(quarantine_path) = quarantine_path {
+                    write!(f, " A copy was saved to {}.", quarantine_path.display())?;
+                }
+                write!(
+                    f,
+                    " Fix the file by hand, or run 'efficacy recover' to go back to the version before the last save."
+                )
+            }
+            EfficacyError::NoBackupError(path) => write!(
+                f,
+                "Could not read {} and there is no readable backup of it to recover from.",
+                path.display()
+            ),
+            EfficacyError::ImportError { line, message } => {
+                write!(f, "Could not import line {}: {}. Nothing was imported.", line, message)
+            }
+            EfficacyError::ExportOnlyFormatError(format) => {
+                write!(f, "{} files can be exported, but not imported.", format)
+            }
+            EfficacyError::ImportOnlyFormatError(format) => {
+                write!(f, "{} files can be imported, but not exported.", format)
+            }
+            EfficacyError::TrashError(message) => write!(f, "Could not restore from the trash: {}.", message),
+            EfficacyError::CyclicParentError => write!(
+                f,
+                "A task cannot be a subtask of itself or of one of its own subtasks. Nothing has been changed."
+            ),
+            EfficacyError::CyclicDependencyError => write!(
+                f,
+                "A task cannot depend on itself or on a task that depends on it. Nothing has been changed."
+            ),
+            EfficacyError::CompletedTaskError(id) => write!(f, "Task #{} is already done.", id),
+            EfficacyError::RecurrenceError(id) => write!(
+                f,
+                "Task #{} recurs too far into the future for its next occurrence to have a due date. Nothing has been changed.",
+                id
+            ),
+            EfficacyError::QueryError(message) => write!(f, "Invalid filter: {}.", message),
+            EfficacyError::SqliteError(e) => write!(f, "The task database could not be used: {}", e),
+            _ => write!(f, "An error occurred:"),
+        }
     }
 }
 
@@ -36,6 +126,12 @@ impl std::convert::From<serde_json::Error> for EfficacyError {
     }
 }
 
+impl std::convert::From<rusqlite::Error> for EfficacyError {
+    fn from(sqlite_error: rusqlite::Error) -> EfficacyError {
+        EfficacyError::SqliteError(sqlite_error)
+    }
+}
+
 // impl std::convert::From<std::option::NoneError> for EfficacyError {
 //     fn from(none_error: std::option::NoneError) -> EfficacyError {
 //         EfficacyError::
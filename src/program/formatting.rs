@@ -1,11 +1,13 @@
-use super::objects::{Task, TaskState};
+use super::objects::{Priority, Task, TaskState};
+use super::timesheet::{self, TimeEntry};
+use super::trash::TrashEntry;
 use chrono::{DateTime, Duration, Local, Utc};
 use colored::Colorize;
 
-const TASK_CHARS: [char; 4] = ['b', 'd', 'i', 'D'];
+const TASK_CHARS: [char; 7] = ['b', 'd', 'i', 'D', 'p', 't', 'a'];
 const DATE_DISPLAY_FORMAT: &str = "%F %R";
 
-pub fn format_task(format_string: &String, to_format: &Task, id: usize) -> String {
+pub fn format_task(format_string: &str, to_format: &Task) -> String {
     let mut new_string = String::new();
     new_string.push_str(format_string);
 
@@ -30,7 +32,17 @@ pub fn format_task(format_string: &String, to_format: &Task, id: usize) -> Strin
         new_string = new_string.replace("%d", &to_format.description[..]);
     }
 
-    new_string = new_string.replace("%i", &format!("#{}", id).bright_black().to_string());
+    new_string = new_string.replace("%i", &format!("#{}", to_format.id).bright_black().to_string());
+    // Without a priority the space after it goes too, so the default format stays aligned
+    if to_format.priority == Priority::None {
+        new_string = new_string.replace("%p ", "");
+    }
+    new_string = new_string.replace("%p", &format_priority(to_format.priority));
+    new_string = new_string.replace("%t", &format_tags(to_format));
+    new_string = match to_format.created {
+        Some(c) => new_string.replace("%a", &format_age(c).bright_black().to_string()),
+        None => new_string.replace("%a", ""),
+    };
     new_string = match to_format.due {
         Some(d) => match &to_format.state {
             TaskState::Done => new_string.replace("%D", &format!("{}", d.with_timezone(&Local).format(DATE_DISPLAY_FORMAT)).bright_black().to_string()),
@@ -39,16 +51,73 @@ pub fn format_task(format_string: &String, to_format: &Task, id: usize) -> Strin
         None => new_string.replace("-> %D", ""),
     };
 
+    if to_format.is_running() {
+        new_string.truncate(new_string.trim_end().len());
+        new_string.push(' ');
+        new_string.push_str(&format_running(to_format));
+    }
+
     match &to_format.state {
         TaskState::Done => new_string.bright_black().to_string(),
         _ => new_string
     }
 }
 
+/// Marks the task the clock is running on, with how long it has been running.
+pub fn format_running(task: &Task) -> String {
+    let started = task.time_log.last().map_or_else(Utc::now, |i| i.start);
+
+    format!("[running {}]", format_duration(Utc::now() - started))
+        .green()
+        .to_string()
+}
+
+/// A length of time as hours and minutes, such as "2:05".
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = std::cmp::max(duration.num_minutes(), 0);
+
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+pub fn format_priority(priority: Priority) -> String {
+    match priority {
+        Priority::High => String::from("!!!").red().bold().to_string(),
+        Priority::Medium => String::from("!!").yellow().to_string(),
+        Priority::Low => String::from("!").blue().to_string(),
+        Priority::None => String::new(),
+    }
+}
+
+pub fn format_tags(task: &Task) -> String {
+    task.tags
+        .iter()
+        .map(|t| format!("+{}", t).cyan().to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Short form of how long ago `since` was, such as "3d" or "2w".
+pub fn format_age(since: DateTime<Utc>) -> String {
+    let age = Utc::now() - since;
+
+    if age < Duration::hours(1) {
+        format!("{}m", std::cmp::max(age.num_minutes(), 0))
+    } else if age < Duration::days(1) {
+        format!("{}h", age.num_hours())
+    } else if age < Duration::weeks(2) {
+        format!("{}d", age.num_days())
+    } else if age < Duration::weeks(8) {
+        format!("{}w", age.num_weeks())
+    } else if age < Duration::weeks(52) {
+        format!("{}mo", age.num_weeks() / 4)
+    } else {
+        format!("{}y", age.num_weeks() / 52)
+    }
+}
+
 pub fn format_due_date(due: DateTime<Utc>, state: &TaskState) -> String {
-    match state {
-        TaskState::Done => return String::from("done").bright_black().to_string(),
-        _ => ()
+    if state == &TaskState::Done {
+        return String::from("done").bright_black().to_string();
     }
 
     let now = Utc::now();
@@ -125,7 +194,7 @@ pub fn format_due_date(due: DateTime<Utc>, state: &TaskState) -> String {
     new_string
 }
 
-pub fn format_task_spotlight(task: &Task, id: usize) -> String {
+pub fn format_task_spotlight(task: &Task, subtasks: &[&Task], open_blockers: &[usize]) -> String {
     let mut new_string = String::from("\n");
 
     match task.state {
@@ -156,11 +225,88 @@ pub fn format_task_spotlight(task: &Task, id: usize) -> String {
         .bright_black()
         .to_string(),
     );
-    new_string.push_str(&format!("id: #{}\n", id).bright_black().to_string());
+    if !task.tags.is_empty() {
+        new_string.push_str(
+            &format!(
+                "tags: {}\n",
+                task.tags.iter().cloned().collect::<Vec<String>>().join(", ")
+            )
+            .bright_black()
+            .to_string(),
+        );
+    }
+    if let Some(parent) = task.parent {
+        new_string.push_str(&format!("parent: #{}\n", parent).bright_black().to_string());
+    }
+    if !subtasks.is_empty() {
+        let done = subtasks
+            .iter()
+            .filter(|t| t.state == TaskState::Done)
+            .count();
+        new_string.push_str(&format!(
+            "{}/{} subtasks done\n",
+            done,
+            subtasks.len()
+        ));
+    }
+    if !task.depends_on.is_empty() {
+        new_string.push_str(
+            &format!(
+                "depends on: {}\n",
+                task.depends_on
+                    .iter()
+                    .map(|id| format!("#{}", id))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .bright_black()
+            .to_string(),
+        );
+    }
+    if !open_blockers.is_empty() && task.state == TaskState::Todo {
+        new_string.push_str(
+            &format!(
+                "blocked by: {}\n",
+                open_blockers
+                    .iter()
+                    .map(|id| format!("#{}", id))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .yellow()
+            .to_string(),
+        );
+    }
+    if let Some(rule) = &task.recurrence {
+        new_string.push_str(&format!("repeats: {}\n", rule).bright_black().to_string());
+    }
+    new_string.push_str(
+        &format!("priority: {}\n", task.priority)
+            .bright_black()
+            .to_string(),
+    );
+    new_string.push_str(&format!("id: #{}\n", task.id).bright_black().to_string());
+    for (label, timestamp) in &[
+        ("created", task.created),
+        ("modified", task.modified),
+        ("completed", task.completed),
+    ] {
+        if let Some(t) = timestamp {
+            new_string.push_str(
+                &format!(
+                    "{}: {} ({} ago)\n",
+                    label,
+                    t.with_timezone(&Local).format(DATE_DISPLAY_FORMAT),
+                    format_age(*t)
+                )
+                .bright_black()
+                .to_string(),
+            );
+        }
+    }
     new_string.push('\n');
     new_string.push_str(
-        &task
-            .information
+        task.information
             .as_ref()
             .unwrap_or(&String::from("No information.").bright_black().to_string()),
     );
@@ -169,7 +315,21 @@ pub fn format_task_spotlight(task: &Task, id: usize) -> String {
     new_string
 }
 
-pub fn format_category(category: &String, ids: &Vec<usize>) -> String {
+/// Dims a formatted task line and marks it as blocked.
+pub fn format_blocked(task_line: &str) -> String {
+    format!("{} {}", task_line.trim_end().dimmed(), "(blocked)".yellow())
+}
+
+/// Indents every line of `to_indent` by two spaces per level of `depth`.
+pub fn indent(to_indent: &str, depth: usize) -> String {
+    let padding = "  ".repeat(depth);
+    to_indent
+        .split_inclusive('\n')
+        .map(|line| format!("{}{}", padding, line))
+        .collect()
+}
+
+pub fn format_category(category: &str, ids: &[usize]) -> String {
     let mut new_string = String::new();
 
     new_string.push_str(&category.bold().to_string());
@@ -178,27 +338,103 @@ pub fn format_category(category: &String, ids: &Vec<usize>) -> String {
     new_string
 }
 
-pub fn format_context(context: &String, is_current: bool) -> String {
+pub fn format_context(context: &str, is_current: bool) -> String {
     let mut new_string = String::new();
 
     if is_current {
         new_string.push_str(&format!("~{}~", &context.italic().to_string()));
     } else {
-        new_string.push_str(&context.to_string());
+        new_string.push_str(context);
     }
 
     new_string
 }
 
+pub fn format_trash_entry(entry: &TrashEntry) -> String {
+    format!(
+        "#{} {} \"{}\" {}",
+        entry.id,
+        entry.kind(),
+        entry.name(),
+        format!(
+            "({} tasks) from {}, deleted {} ago",
+            entry.task_count(),
+            entry.context,
+            format_age(entry.deleted)
+        )
+        .bright_black()
+    )
+}
+
+/// Time spent per day, per category and per task, with the total.
+pub fn format_time_report(entries: &[TimeEntry]) -> String {
+    if entries.is_empty() {
+        return String::from("No time recorded.").bright_black().to_string();
+    }
+
+    let by_day: Vec<(String, Duration)> = timesheet::totals_by(entries, |e| e.date)
+        .into_iter()
+        .map(|(date, d)| (date.format("%a %F").to_string(), d))
+        .collect();
+    let by_category: Vec<(String, Duration)> = timesheet::totals_by(entries, |e| {
+        e.category
+            .clone()
+            .unwrap_or_else(|| String::from("No category"))
+    });
+    let by_task: Vec<(String, Duration)> = timesheet::totals_by(entries, |e| e.task_id)
+        .into_iter()
+        .map(|(id, d)| {
+            let entry = entries.iter().find(|e| e.task_id == id).unwrap();
+            (format!("#{} {}", id, entry.description), d)
+        })
+        .collect();
+    let total = entries
+        .iter()
+        .fold(Duration::zero(), |total, e| total + e.duration);
+
+    let mut new_string = String::from("\n");
+    for (heading, rows) in &[
+        ("Days", by_day),
+        ("Categories", by_category),
+        ("Tasks", by_task),
+    ] {
+        new_string.push_str(&format!("{}:\n", heading.bold()));
+
+        let width = rows
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (name, duration) in rows {
+            new_string.push_str(&format!(
+                "{:width$}  {:>6}\n",
+                name,
+                format_duration(*duration),
+                width = width
+            ));
+        }
+        new_string.push('\n');
+    }
+    new_string.push_str(&format!("{} {}", "Total:".bold(), format_duration(total)));
+
+    new_string
+}
+
+pub fn format_empty_trash() -> String {
+    String::from("The trash is empty.")
+        .bright_black()
+        .to_string()
+}
+
 pub fn format_nothing() -> String {
     String::from("No tasks!").bright_black().to_string()
 }
 
-pub fn valid_task_format(format: &String) -> bool {
+pub fn valid_task_format(format: &str) -> bool {
     valid(format, TASK_CHARS.to_vec())
 }
 
-fn valid(format: &String, valid_letters: Vec<char>) -> bool {
+fn valid(format: &str, valid_letters: Vec<char>) -> bool {
     let mut follows_escape = false;
     for character in format.chars() {
         if follows_escape {
@@ -222,6 +458,23 @@ mod tests {
         use super::*;
 
         assert!(valid_task_format(&String::from("%b %d")));
-        assert!(!valid_task_format(&String::from("%a %z")));
+        assert!(valid_task_format(&String::from("%b %p %d %t %a")));
+        assert!(!valid_task_format(&String::from("%b %z")));
+    }
+
+    #[test]
+    fn priority_token() {
+        use super::*;
+
+        let mut task = Task::new(String::from("Write report"));
+        assert_eq!(format_task("%b %p %d", &task), "[ ] Write report");
+
+        task.priority = Priority::High;
+        let line = format_task("%b %p %d", &task);
+        assert!(line.starts_with("[ ] "));
+        assert!(line.contains("!!!"));
+        assert!(line.ends_with(" Write report"));
+        task.priority = Priority::Low;
+        assert!(!format_task("%b %p %d", &task).contains("!!"));
     }
 }
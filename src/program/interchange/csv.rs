@@ -0,0 +1,120 @@
+use super::super::{objects::Task, output::TaskRecord};
+
+const COLUMNS: [&str; 16] = [
+    "context",
+    "id",
+    "description",
+    "state",
+    "priority",
+    "category",
+    "tags",
+    "information",
+    "due",
+    "recurrence",
+    "recur_from",
+    "parent",
+    "depends_on",
+    "created",
+    "modified",
+    "completed",
+];
+
+/// Writes an RFC 4180 file with a header row and one row per task. Values are written as they are
+/// in JSON output, with tags and dependencies separated by commas and timestamps in RFC 3339.
+pub fn export(tasks: &[Task], context: &str) -> String {
+    let mut rows = vec![COLUMNS
+        .iter()
+        .map(|c| String::from(*c))
+        .collect::<Vec<String>>()];
+
+    for task in tasks {
+        let record = TaskRecord::new(task, context, None, false);
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let timestamp = |value: Option<chrono::DateTime<chrono::Utc>>| {
+            optional(value.map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)))
+        };
+        let list = |values: Vec<String>| values.join(",");
+
+        rows.push(vec![
+            record.context,
+            record.id.to_string(),
+            record.description,
+            record.state,
+            record.priority,
+            optional(record.category),
+            list(record.tags),
+            optional(record.information),
+            timestamp(record.due),
+            optional(record.recurrence),
+            optional(record.recur_from),
+            optional(record.parent.map(|p| p.to_string())),
+            list(record.depends_on.iter().map(|d| d.to_string()).collect()),
+            timestamp(record.created),
+            timestamp(record.modified),
+            timestamp(record.completed),
+        ]);
+    }
+
+    write_rows(&rows)
+}
+
+/// Writes rows of fields as lines of an RFC 4180 file.
+pub fn write_rows(rows: &[Vec<String>]) -> String {
+    rows.iter()
+        .map(|row| {
+            let fields: Vec<String> = row.iter().map(|f| quote(f)).collect();
+            fields.join(",") + "\r\n"
+        })
+        .collect()
+}
+
+/// Quotes a field if it contains anything that would otherwise end it.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::objects::{Priority, TaskState};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn writes_one_row_per_task() {
+        let mut task = Task::new(String::from("Write \"the\" report, finally"));
+        task.id = 2;
+        task.state = TaskState::Done;
+        task.priority = Priority::High;
+        task.category = Some(String::from("Work"));
+        task.tags.insert(String::from("q4"));
+        task.tags.insert(String::from("urgent"));
+        task.information = Some(String::from("Two\nlines"));
+        task.due = Some(Utc.ymd(2026, 11, 3).and_hms(8, 0, 0));
+        task.parent = Some(1);
+        task.depends_on.insert(0);
+        task.created = None;
+        task.modified = None;
+
+        let exported = export(&[task], "default");
+        let mut rows = exported.split("\r\n");
+        assert_eq!(
+            rows.next(),
+            Some(
+                "context,id,description,state,priority,category,tags,information,due,recurrence,\
+recur_from,parent,depends_on,created,modified,completed"
+            )
+        );
+        assert_eq!(
+            rows.next(),
+            Some(
+                "default,2,\"Write \"\"the\"\" report, finally\",done,high,Work,\"q4,urgent\",\
+\"Two\nlines\",2026-11-03T08:00:00Z,,,1,0,,,"
+            )
+        );
+        assert_eq!(rows.next(), Some(""));
+    }
+}
@@ -0,0 +1,587 @@
+use chrono::{
+    DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use std::collections::HashMap;
+
+use super::super::{
+    errors::EfficacyError,
+    objects::{Priority, RecurFrom, Recurrence, RecurrenceRule, Task, TaskState},
+    EfficacyResult,
+};
+
+const UTC_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const LOCAL_FORMAT: &str = "%Y%m%dT%H%M%S";
+const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Longest a content line may be, in bytes, before it has to be folded.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Writes an RFC 5545 calendar with a VTODO for every task. The category comes first in
+/// CATEGORIES, followed by the tags. Subtasks and dependencies are written as RELATED-TO with
+/// the PARENT and DEPENDS-ON relation types, pointing at UIDs made from the context and ID.
+pub fn export(tasks: &[Task], context: &str) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        format!(
+            "PRODID:-//efficacy//efficacy {}//EN",
+            env!("CARGO_PKG_VERSION")
+        ),
+    ];
+    let now = Utc::now().format(UTC_FORMAT).to_string();
+
+    for task in tasks {
+        lines.push(String::from("BEGIN:VTODO"));
+        lines.push(format!("UID:{}", uid(context, task.id)));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("SUMMARY:{}", escape(&task.description)));
+
+        if let Some(information) = task.information.as_ref().filter(|i| !i.is_empty()) {
+            lines.push(format!("DESCRIPTION:{}", escape(information)));
+        }
+
+        let categories: Vec<String> = task
+            .category
+            .iter()
+            .chain(task.tags.iter())
+            .map(|c| escape(c))
+            .collect();
+        if !categories.is_empty() {
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+
+        match task.state {
+            TaskState::Todo => lines.push(String::from("STATUS:NEEDS-ACTION")),
+            TaskState::Done => {
+                lines.push(String::from("STATUS:COMPLETED"));
+                if let Some(completed) = task.completed {
+                    lines.push(format!("COMPLETED:{}", completed.format(UTC_FORMAT)));
+                }
+            }
+        }
+
+        if let Some(priority) = priority_number(task.priority) {
+            lines.push(format!("PRIORITY:{}", priority));
+        }
+        if let Some(due) = task.due {
+            lines.push(format!("DUE:{}", due.format(UTC_FORMAT)));
+        }
+        if let Some(rule) = &task.recurrence {
+            lines.push(format!("RRULE:{}", rrule(&rule.every)));
+        }
+        if let Some(parent) = task.parent {
+            lines.push(format!(
+                "RELATED-TO;RELTYPE=PARENT:{}",
+                uid(context, parent)
+            ));
+        }
+        for dependency in &task.depends_on {
+            lines.push(format!(
+                "RELATED-TO;RELTYPE=DEPENDS-ON:{}",
+                uid(context, *dependency)
+            ));
+        }
+        if let Some(created) = task.created {
+            lines.push(format!("CREATED:{}", created.format(UTC_FORMAT)));
+        }
+        if let Some(modified) = task.modified {
+            lines.push(format!("LAST-MODIFIED:{}", modified.format(UTC_FORMAT)));
+        }
+
+        lines.push(String::from("END:VTODO"));
+    }
+
+    lines.push(String::from("END:VCALENDAR"));
+
+    let mut result = String::new();
+    for line in lines {
+        result.push_str(&fold(&line));
+        result.push_str("\r\n");
+    }
+    result
+}
+
+/// Reads every VTODO in a calendar, ignoring other components. The first of the CATEGORIES
+/// becomes the category and the rest become tags. Repeat rules that efficacy can't express are
+/// dropped.
+pub fn import(contents: &str, default_time: NaiveTime) -> EfficacyResult<Vec<Task>> {
+    let mut tasks = Vec::new();
+    let mut uids = HashMap::new();
+    // Relations are resolved once every UID is known: (task index, UID, is parent)
+    let mut relations = Vec::new();
+
+    let mut current: Option<(Task, usize)> = None;
+    // How deep we are in components nested inside the current VTODO, such as VALARM
+    let mut nested = 0;
+
+    for (line_number, line) in unfold(contents) {
+        let error = |message: String| EfficacyError::ImportError {
+            line: line_number,
+            message,
+        };
+        let property = match parse_property(&line) {
+            Some(p) => p,
+            None => return Err(error(format!("'{}' is not a content line", line))),
+        };
+
+        match (&property.name[..], &mut current) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VTODO") => {
+                current = Some((Task::new(String::new()), line_number));
+            }
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) => {
+                let (mut task, start) = current.take().unwrap();
+                if task.description.is_empty() {
+                    return Err(EfficacyError::ImportError {
+                        line: start,
+                        message: String::from("The VTODO has no SUMMARY"),
+                    });
+                }
+                task.id = tasks.len();
+                tasks.push(task);
+            }
+            (_, Some(_)) if nested > 0 => (),
+            (name, Some((task, _))) => {
+                let value = &property.value;
+                match name {
+                    "UID" => {
+                        uids.insert(value.clone(), tasks.len());
+                    }
+                    "SUMMARY" => task.description = unescape(value),
+                    "DESCRIPTION" => task.information = Some(unescape(value)),
+                    "CATEGORIES" => {
+                        for category in split_list(value) {
+                            if task.category.is_none() {
+                                task.category = Some(category);
+                            } else {
+                                task.tags.insert(category);
+                            }
+                        }
+                    }
+                    "STATUS" => {
+                        task.state = match &value.to_uppercase()[..] {
+                            "COMPLETED" | "CANCELLED" => TaskState::Done,
+                            _ => TaskState::Todo,
+                        }
+                    }
+                    "PRIORITY" => {
+                        task.priority = match value.trim().parse::<u32>() {
+                            Ok(p) => number_priority(p),
+                            Err(_) => return Err(error(format!("'{}' is not a priority", value))),
+                        }
+                    }
+                    "DUE" | "COMPLETED" | "CREATED" | "LAST-MODIFIED" => {
+                        let time = match parse_time(value, default_time) {
+                            Some(t) => t,
+                            None => return Err(error(format!("'{}' is not a date", value))),
+                        };
+                        match name {
+                            "DUE" => task.due = Some(time),
+                            "COMPLETED" => task.completed = Some(time),
+                            "CREATED" => task.created = Some(time),
+                            _ => task.modified = Some(time),
+                        }
+                    }
+                    "RRULE" => {
+                        task.recurrence = parse_rrule(value).map(|every| RecurrenceRule {
+                            every,
+                            from: RecurFrom::Due,
+                        })
+                    }
+                    "RELATED-TO" => {
+                        let relation = property
+                            .parameters
+                            .get("RELTYPE")
+                            .map(|r| r.to_uppercase())
+                            .unwrap_or_else(|| String::from("PARENT"));
+                        match &relation[..] {
+                            "PARENT" => relations.push((tasks.len(), value.clone(), true)),
+                            "DEPENDS-ON" => relations.push((tasks.len(), value.clone(), false)),
+                            _ => (),
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if let Some((_, start)) = current {
+        return Err(EfficacyError::ImportError {
+            line: start,
+            message: String::from("The VTODO is never ended"),
+        });
+    }
+
+    for (index, related_uid, is_parent) in relations {
+        let related = match uids.get(&related_uid) {
+            Some(r) => *r,
+            None => continue,
+        };
+        if is_parent {
+            tasks[index].parent = Some(related);
+        } else {
+            tasks[index].depends_on.insert(related);
+        }
+    }
+
+    Ok(tasks)
+}
+
+struct Property {
+    name: String,
+    parameters: HashMap<String, String>,
+    value: String,
+}
+
+/// Splits `NAME;PARAM=VALUE:value`, allowing quoted parameter values to contain `:` and `;`.
+fn parse_property(line: &str) -> Option<Property> {
+    let mut in_quotes = false;
+    let mut colon = None;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                colon = Some(index);
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let colon = colon?;
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+
+    let parameters = head
+        .filter_map(|p| {
+            let mut parts = p.splitn(2, '=');
+            Some((
+                parts.next()?.trim().to_uppercase(),
+                parts.next()?.trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+
+    Some(Property {
+        name,
+        parameters,
+        value: String::from(&line[colon + 1..]),
+    })
+}
+
+/// Joins folded lines back together, keeping the line number each one started on.
+fn unfold(contents: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, last)) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        if !line.trim().is_empty() {
+            lines.push((index + 1, String::from(line)));
+        }
+    }
+
+    lines
+}
+
+/// Breaks a content line into lines of at most 75 bytes, without splitting characters.
+fn fold(line: &str) -> String {
+    let mut result = String::new();
+    let mut length = 0;
+
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            result.push_str("\r\n ");
+            length = 1;
+        }
+        result.push(c);
+        length += c.len_utf8();
+    }
+
+    result
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// Splits a comma separated value, leaving escaped commas alone.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+
+    for c in value.chars() {
+        if escaped {
+            current.push('\\');
+            current.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ',' {
+            items.push(unescape(&current));
+            current.clear();
+        } else {
+            current.push(c);
+        }
+    }
+    items.push(unescape(&current));
+
+    items
+        .into_iter()
+        .map(|i| String::from(i.trim()))
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+/// UTC times end in `Z`. Times without it are taken as local time, and dates on their own are
+/// given `default_time`.
+fn parse_time(value: &str, default_time: NaiveTime) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+
+    if let Ok(time) = Utc.datetime_from_str(value, UTC_FORMAT) {
+        return Some(time);
+    }
+
+    let local = match NaiveDateTime::parse_from_str(value, LOCAL_FORMAT) {
+        Ok(t) => t,
+        Err(_) => NaiveDate::parse_from_str(value, DATE_FORMAT)
+            .ok()?
+            .and_time(default_time),
+    };
+
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn uid(context: &str, id: usize) -> String {
+    format!("{}-{}@efficacy", context, id)
+}
+
+fn priority_number(priority: Priority) -> Option<u32> {
+    match priority {
+        Priority::High => Some(1),
+        Priority::Medium => Some(5),
+        Priority::Low => Some(9),
+        Priority::None => None,
+    }
+}
+
+/// 1 to 4 are high, 5 is medium and 6 to 9 are low. 0 means undefined.
+fn number_priority(number: u32) -> Priority {
+    match number {
+        1..=4 => Priority::High,
+        5 => Priority::Medium,
+        6..=9 => Priority::Low,
+        _ => Priority::None,
+    }
+}
+
+const ICAL_WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Mon),
+    ("TU", Weekday::Tue),
+    ("WE", Weekday::Wed),
+    ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri),
+    ("SA", Weekday::Sat),
+    ("SU", Weekday::Sun),
+];
+
+fn rrule(recurrence: &Recurrence) -> String {
+    match recurrence {
+        Recurrence::Daily => String::from("FREQ=DAILY"),
+        Recurrence::EveryNDays(n) => format!("FREQ=DAILY;INTERVAL={}", n),
+        Recurrence::Weekly(days) if days.is_empty() => String::from("FREQ=WEEKLY"),
+        Recurrence::Weekly(days) => format!(
+            "FREQ=WEEKLY;BYDAY={}",
+            days.iter()
+                .filter_map(|d| ICAL_WEEKDAYS.iter().find(|(_, w)| w == d))
+                .map(|(name, _)| *name)
+                .collect::<Vec<&str>>()
+                .join(",")
+        ),
+        Recurrence::Monthly(day) => format!("FREQ=MONTHLY;BYMONTHDAY={}", day),
+    }
+}
+
+fn parse_rrule(value: &str) -> Option<Recurrence> {
+    let parts: HashMap<String, String> = value
+        .split(';')
+        .filter_map(|p| {
+            let mut kv = p.splitn(2, '=');
+            Some((kv.next()?.to_uppercase(), kv.next()?.to_uppercase()))
+        })
+        .collect();
+
+    let interval: u32 = match parts.get("INTERVAL") {
+        Some(i) => i.parse().ok().filter(|i| *i >= 1)?,
+        None => 1,
+    };
+
+    match (parts.get("FREQ").map(|f| &f[..]), interval) {
+        (Some("DAILY"), 1) => Some(Recurrence::Daily),
+        (Some("DAILY"), n) => Some(Recurrence::EveryNDays(n)),
+        (Some("WEEKLY"), 1) => {
+            let days = match parts.get("BYDAY") {
+                Some(days) => days
+                    .split(',')
+                    .map(|d| {
+                        ICAL_WEEKDAYS
+                            .iter()
+                            .find(|(name, _)| d.ends_with(name))
+                            .map(|(_, w)| *w)
+                    })
+                    .collect::<Option<Vec<Weekday>>>()?,
+                None => Vec::new(),
+            };
+            Some(Recurrence::Weekly(days))
+        }
+        (Some("WEEKLY"), n) if !parts.contains_key("BYDAY") => {
+            n.checked_mul(7).map(Recurrence::EveryNDays)
+        }
+        (Some("MONTHLY"), 1) => match parts.get("BYMONTHDAY") {
+            Some(day) => day
+                .parse()
+                .ok()
+                .filter(|d| (1..=31).contains(d))
+                .map(Recurrence::Monthly),
+            None => Some(Recurrence::Monthly(Local::today().day())),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Timelike;
+
+    #[test]
+    fn round_trips_vtodos() {
+        let mut parent = Task::new(String::from("Plan the offsite; book rooms, food"));
+        parent.id = 3;
+        parent.information = Some(String::from(
+            "Budget is in the shared sheet.\nAsk finance first.",
+        ));
+        parent.category = Some(String::from("Work"));
+        parent.tags.insert(String::from("q4"));
+        parent.priority = Priority::High;
+        parent.due = Some(Utc.ymd(2026, 11, 3).and_hms(17, 0, 0));
+        parent.recurrence = Some(RecurrenceRule {
+            every: Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]),
+            from: RecurFrom::Due,
+        });
+
+        let mut child = Task::new(String::from("Book rooms ").repeat(10));
+        child.id = 7;
+        child.state = TaskState::Done;
+        child.completed = Some(Utc.ymd(2026, 10, 20).and_hms(9, 30, 0));
+        child.parent = Some(3);
+        child.depends_on.insert(3);
+
+        let exported = export(&[parent.clone(), child.clone()], "default");
+        assert!(exported.lines().all(|l| l.len() <= MAX_LINE_LENGTH + 1));
+        assert!(exported.contains("UID:default-3@efficacy\r\n"));
+
+        let imported = import(&exported, NaiveTime::from_hms(8, 0, 0)).unwrap();
+        assert_eq!(imported.len(), 2);
+
+        assert_eq!(imported[0].description, parent.description);
+        assert_eq!(imported[0].information, parent.information);
+        assert_eq!(imported[0].category, parent.category);
+        assert_eq!(imported[0].tags, parent.tags);
+        assert_eq!(imported[0].priority, Priority::High);
+        assert_eq!(imported[0].due, parent.due);
+        assert_eq!(imported[0].recurrence, parent.recurrence);
+
+        assert_eq!(imported[1].description, child.description);
+        assert_eq!(imported[1].state, TaskState::Done);
+        assert_eq!(imported[1].completed, child.completed);
+        // Relations point at the position of the imported task
+        assert_eq!(imported[1].parent, Some(0));
+        assert!(imported[1].depends_on.contains(&0));
+    }
+
+    #[test]
+    fn reads_other_calendars() {
+        let calendar = "BEGIN:VCALENDAR\n\
+VERSION:2.0\n\
+BEGIN:VEVENT\n\
+SUMMARY:Not a task\n\
+END:VEVENT\n\
+BEGIN:VTODO\n\
+SUMMARY:Renew passport\n\
+DUE;VALUE=DATE:20261201\n\
+PRIORITY:7\n\
+BEGIN:VALARM\n\
+DESCRIPTION:Reminder\n\
+END:VALARM\n\
+END:VTODO\n\
+BEGIN:VTODO\n\
+DUE:20261201T100000Z\n\
+END:VTODO\n\
+END:VCALENDAR\n";
+
+        match import(calendar, NaiveTime::from_hms(8, 0, 0)) {
+            Err(EfficacyError::ImportError { line: 14, .. }) => (),
+            other => panic!("Expected an ImportError, got {:?}", other),
+        }
+
+        let valid: String = calendar
+            .lines()
+            .take(13)
+            .map(|l| format!("{}\n", l))
+            .collect();
+        let tasks = import(&valid, NaiveTime::from_hms(8, 0, 0)).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].description, "Renew passport");
+        assert_eq!(tasks[0].information, None);
+        assert_eq!(tasks[0].priority, Priority::Low);
+        let due = tasks[0].due.unwrap().with_timezone(&Local);
+        assert_eq!((due.day(), due.hour()), (1, 8));
+    }
+
+    #[test]
+    fn oversized_intervals_are_ignored() {
+        assert_eq!(
+            parse_rrule("FREQ=WEEKLY;INTERVAL=3"),
+            Some(Recurrence::EveryNDays(21))
+        );
+        assert_eq!(parse_rrule("FREQ=WEEKLY;INTERVAL=1000000000"), None);
+    }
+}
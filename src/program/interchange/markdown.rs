@@ -0,0 +1,130 @@
+use chrono::Local;
+use std::collections::BTreeMap;
+
+use super::super::{
+    formatting,
+    objects::{Priority, Task, TaskState},
+    state,
+};
+
+/// Writes the context as a heading, with a heading per category beneath it. Each task is a
+/// GitHub checkbox item laid out by `task_format`, as in listings, and subtasks are nested under
+/// their parent when it is in the same category.
+pub fn export(tasks: &[Task], context: &str, task_format: &str) -> String {
+    let mut categories: BTreeMap<String, Vec<&Task>> = BTreeMap::new();
+    for task in tasks {
+        categories
+            .entry(state::category_key(task))
+            .or_default()
+            .push(task);
+    }
+
+    let mut result = format!("# {}\n", context);
+
+    for (category, members) in categories {
+        result.push_str(&format!("\n## {}\n\n", category));
+
+        let ids: Vec<usize> = members.iter().map(|t| t.id).collect();
+        for task in &members {
+            let nested = task.parent.is_some_and(|p| ids.contains(&p));
+            if !nested {
+                push_task_tree(&mut result, &members, task, 0, task_format);
+            }
+        }
+    }
+
+    result
+}
+
+fn push_task_tree(
+    result: &mut String,
+    members: &[&Task],
+    task: &Task,
+    depth: usize,
+    task_format: &str,
+) {
+    result.push_str(&"  ".repeat(depth));
+    result.push_str(&format_item(task_format, task));
+    result.push('\n');
+
+    for subtask in members.iter().filter(|t| t.parent == Some(task.id)) {
+        push_task_tree(result, members, subtask, depth + 1, task_format);
+    }
+}
+
+/// The plain text counterpart of `formatting::format_task`. The checkbox always comes first so
+/// that it renders, and due dates are written out since the list is read later.
+fn format_item(task_format: &str, task: &Task) -> String {
+    let checkbox = match task.state {
+        TaskState::Todo => "- [ ]",
+        TaskState::Done => "- [x]",
+    };
+
+    let mut line = task_format.replace("%b", "");
+    line = match task.due {
+        Some(due) => line.replace("%D", &due.with_timezone(&Local).format("%F %R").to_string()),
+        None => line.replace("-> %D", "").replace("%D", ""),
+    };
+    line = line
+        .replace("%d", &task.description)
+        .replace("%i", &format!("#{}", task.id))
+        .replace("%p", priority_marks(task.priority))
+        .replace(
+            "%t",
+            &task
+                .tags
+                .iter()
+                .map(|t| format!("+{}", t))
+                .collect::<Vec<String>>()
+                .join(" "),
+        )
+        .replace(
+            "%a",
+            &task.created.map(formatting::format_age).unwrap_or_default(),
+        );
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    format!("{} {}", checkbox, words.join(" "))
+}
+
+fn priority_marks(priority: Priority) -> &'static str {
+    match priority {
+        Priority::High => "!!!",
+        Priority::Medium => "!!",
+        Priority::Low => "!",
+        Priority::None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_checkboxes_under_categories() {
+        let mut report = Task::new(String::from("Write report"));
+        report.id = 0;
+        report.category = Some(String::from("Work"));
+        report.priority = Priority::High;
+
+        let mut draft = Task::new(String::from("Draft outline"));
+        draft.id = 1;
+        draft.category = Some(String::from("Work"));
+        draft.parent = Some(0);
+        draft.state = TaskState::Done;
+
+        let mut plants = Task::new(String::from("Water plants"));
+        plants.id = 2;
+        plants.tags.insert(String::from("home"));
+
+        assert_eq!(
+            export(&[report, draft, plants], "default", "%b %p %d %i %t -> %D"),
+            "# default\n\n\
+## No category\n\n\
+- [ ] Water plants #2 +home\n\n\
+## Work\n\n\
+- [ ] !!! Write report #0\n  \
+- [x] Draft outline #1\n"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+mod csv;
+mod ical;
+mod markdown;
+mod mstodo;
+mod taskwarrior;
+mod todoist;
+mod todotxt;
+
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+use super::{errors::EfficacyError, objects::Task, EfficacyResult};
+
+/// File formats tasks can be exported to and imported from.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    TodoTxt,
+    /// RFC 5545 calendars, with a VTODO for each task.
+    ICal,
+    /// Export only.
+    Csv,
+    /// Export only.
+    Markdown,
+    /// The output of `task export`. Import only.
+    Taskwarrior,
+    /// Tasks from the Todoist API. Import only.
+    Todoist,
+    /// Lists and tasks from Microsoft Graph. Import only.
+    MicrosoftToDo,
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Format::TodoTxt => "todo.txt",
+            Format::ICal => "iCalendar",
+            Format::Csv => "CSV",
+            Format::Markdown => "Markdown",
+            Format::Taskwarrior => "Taskwarrior",
+            Format::Todoist => "Todoist",
+            Format::MicrosoftToDo => "Microsoft To Do",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "todotxt" | "todo.txt" => Ok(Format::TodoTxt),
+            "ical" | "ics" => Ok(Format::ICal),
+            "csv" => Ok(Format::Csv),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "taskwarrior" => Ok(Format::Taskwarrior),
+            "todoist" => Ok(Format::Todoist),
+            "mstodo" | "microsoft-todo" => Ok(Format::MicrosoftToDo),
+            _ => Err(format!("'{}' is not a known file format", s)),
+        }
+    }
+}
+
+/// Writes the tasks of `context`, which formats that identify tasks use to keep them apart from
+/// the tasks of other contexts. Formats meant for reading lay tasks out with `task_format`.
+pub fn export(
+    format: Format,
+    tasks: &[Task],
+    context: &str,
+    task_format: &str,
+) -> EfficacyResult<String> {
+    match format {
+        Format::TodoTxt => Ok(todotxt::export(tasks)),
+        Format::ICal => Ok(ical::export(tasks, context)),
+        Format::Csv => Ok(csv::export(tasks, context)),
+        Format::Markdown => Ok(markdown::export(tasks, context, task_format)),
+        Format::Taskwarrior | Format::Todoist | Format::MicrosoftToDo => {
+            Err(EfficacyError::ImportOnlyFormatError(format.to_string()))
+        }
+    }
+}
+
+/// Reads tasks from a file. The IDs of the returned tasks, and the parents and dependencies
+/// pointing at them, only relate the imported tasks to each other. Dates without a time of day
+/// are given `default_time`.
+pub fn import(
+    format: Format,
+    contents: &str,
+    default_time: NaiveTime,
+) -> EfficacyResult<Vec<Task>> {
+    match format {
+        Format::TodoTxt => todotxt::import(contents, default_time),
+        Format::ICal => ical::import(contents, default_time),
+        Format::Taskwarrior => taskwarrior::import(contents),
+        Format::Todoist => todoist::import(contents, default_time),
+        Format::MicrosoftToDo => mstodo::import(contents, default_time),
+        Format::Csv | Format::Markdown => {
+            Err(EfficacyError::ExportOnlyFormatError(format.to_string()))
+        }
+    }
+}
+
+/// Writes rows of fields as a CSV file, for reports that aren't a list of tasks.
+pub fn write_csv(rows: &[Vec<String>]) -> String {
+    csv::write_rows(rows)
+}
+
+/// Reports where JSON exports of other applications couldn't be read.
+fn json_error(error: serde_json::Error) -> EfficacyError {
+    let message = error.to_string();
+    EfficacyError::ImportError {
+        line: error.line(),
+        message: match message.find(" at line ") {
+            Some(index) => String::from(&message[..index]),
+            None => message,
+        },
+    }
+}
+
+/// Times without a time zone are taken to be local.
+fn local_to_utc(time: NaiveDateTime) -> DateTime<Utc> {
+    match Local.from_local_datetime(&time).earliest() {
+        Some(t) => t.with_timezone(&Utc),
+        None => Utc.from_utc_datetime(&time),
+    }
+}
@@ -0,0 +1,276 @@
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use serde::Deserialize;
+
+use super::super::{
+    objects::{Priority, RecurFrom, Recurrence, RecurrenceRule, Task, TaskState},
+    EfficacyResult,
+};
+
+/// Either every list with its tasks, or a single list's tasks as returned by Microsoft Graph.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Lists { lists: Vec<TaskList> },
+    Tasks { value: Vec<TodoTask> },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TaskList {
+    display_name: String,
+    tasks: Vec<TodoTask>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TodoTask {
+    title: String,
+    status: String,
+    #[serde(default)]
+    importance: String,
+    body: Option<Body>,
+    due_date_time: Option<ZonedTime>,
+    completed_date_time: Option<ZonedTime>,
+    created_date_time: Option<String>,
+    last_modified_date_time: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    checklist_items: Vec<ChecklistItem>,
+    recurrence: Option<PatternedRecurrence>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Body {
+    content: String,
+    content_type: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ZonedTime {
+    date_time: String,
+    time_zone: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChecklistItem {
+    display_name: String,
+    is_checked: bool,
+    created_date_time: Option<String>,
+    checked_date_time: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PatternedRecurrence {
+    pattern: RecurrencePattern,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecurrencePattern {
+    #[serde(rename = "type")]
+    kind: String,
+    interval: u32,
+    #[serde(default)]
+    days_of_week: Vec<String>,
+    #[serde(default)]
+    day_of_month: u32,
+}
+
+/// Reads tasks exported from Microsoft To Do through Microsoft Graph. Lists become categories,
+/// categories become tags, notes become the information and checklist steps become subtasks. To
+/// Do only keeps the day tasks are due on, so due dates are given `default_time`.
+pub fn import(contents: &str, default_time: NaiveTime) -> EfficacyResult<Vec<Task>> {
+    let lists = match serde_json::from_str(contents).map_err(super::json_error)? {
+        Export::Lists { lists } => lists
+            .into_iter()
+            .map(|l| (Some(l.display_name), l.tasks))
+            .collect(),
+        Export::Tasks { value } => vec![(None, value)],
+    };
+
+    let mut tasks = Vec::new();
+
+    for (list_name, list_tasks) in lists {
+        for todo_task in list_tasks {
+            let mut task = Task::new(todo_task.title);
+            task.id = tasks.len();
+            task.category = list_name.clone();
+            task.tags = todo_task.categories.into_iter().collect();
+            task.priority = match &todo_task.importance[..] {
+                "high" => Priority::High,
+                "low" => Priority::Low,
+                _ => Priority::None,
+            };
+            if todo_task.status == "completed" {
+                task.state = TaskState::Done;
+                task.completed = todo_task.completed_date_time.as_ref().and_then(parse_zoned);
+            }
+            task.due = todo_task.due_date_time.as_ref().and_then(|due| {
+                let date = parse_zoned_naive(due)?.date();
+                Some(super::local_to_utc(date.and_time(default_time)))
+            });
+            task.created = todo_task.created_date_time.as_deref().and_then(parse_time);
+            task.modified = todo_task
+                .last_modified_date_time
+                .as_deref()
+                .and_then(parse_time);
+            task.information = todo_task
+                .body
+                .map(|b| match &b.content_type.to_lowercase()[..] {
+                    "html" => strip_html(&b.content),
+                    _ => b.content,
+                })
+                .map(|c| String::from(c.trim()))
+                .filter(|c| !c.is_empty());
+            task.recurrence = todo_task
+                .recurrence
+                .and_then(|r| parse_recurrence(&r.pattern));
+
+            let parent = task.id;
+            let category = task.category.clone();
+            tasks.push(task);
+
+            for item in todo_task.checklist_items {
+                let mut subtask = Task::new(item.display_name);
+                subtask.id = tasks.len();
+                subtask.parent = Some(parent);
+                subtask.category = category.clone();
+                subtask.created = item.created_date_time.as_deref().and_then(parse_time);
+                if item.is_checked {
+                    subtask.state = TaskState::Done;
+                    subtask.completed = item.checked_date_time.as_deref().and_then(parse_time);
+                }
+                tasks.push(subtask);
+            }
+        }
+    }
+
+    Ok(tasks)
+}
+
+/// Graph writes times with seven decimal places, and leaves out the offset when it gives the
+/// time zone separately.
+fn parse_zoned_naive(time: &ZonedTime) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(&time.date_time, "%Y-%m-%dT%H:%M:%S%.f").ok()
+}
+
+/// Times in any zone other than UTC are taken to be local.
+fn parse_zoned(time: &ZonedTime) -> Option<DateTime<Utc>> {
+    let naive = parse_zoned_naive(time)?;
+    match &time.time_zone[..] {
+        "UTC" | "Etc/UTC" => Some(Utc.from_utc_datetime(&naive)),
+        _ => Some(super::local_to_utc(naive)),
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+fn parse_recurrence(pattern: &RecurrencePattern) -> Option<RecurrenceRule> {
+    let every = match (&pattern.kind[..], pattern.interval) {
+        ("daily", 1) => Recurrence::Daily,
+        ("daily", n) if n > 1 => Recurrence::EveryNDays(n),
+        ("weekly", 1) => Recurrence::Weekly(
+            pattern
+                .days_of_week
+                .iter()
+                .map(|d| d.parse::<Weekday>().ok())
+                .collect::<Option<Vec<Weekday>>>()?,
+        ),
+        ("weekly", n) if n > 1 && pattern.days_of_week.len() <= 1 => {
+            Recurrence::EveryNDays(n.checked_mul(7)?)
+        }
+        ("absoluteMonthly", 1) if (1..=31).contains(&pattern.day_of_month) => {
+            Recurrence::Monthly(pattern.day_of_month)
+        }
+        _ => return None,
+    };
+
+    Some(RecurrenceRule {
+        every,
+        from: RecurFrom::Due,
+    })
+}
+
+/// Notes edited on some devices come back as HTML. Only the text is kept.
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in html.replace("<br>", "\n").replace("</p>", "\n").chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Local, Timelike};
+
+    #[test]
+    fn reads_microsoft_to_do_export() {
+        let default_time = NaiveTime::from_hms(8, 0, 0);
+        let tasks = import(include_str!("samples/mstodo.json"), default_time).unwrap();
+        assert_eq!(tasks.len(), 4);
+
+        assert_eq!(tasks[0].description, "Plan team offsite");
+        assert_eq!(tasks[0].category.as_deref(), Some("Work"));
+        assert_eq!(tasks[0].priority, Priority::High);
+        assert!(tasks[0].has_tag("Red category"));
+        assert_eq!(
+            tasks[0].information.as_deref(),
+            Some("Budget: 2 days\nAsk about the venue & catering")
+        );
+        let due = tasks[0].due.unwrap().with_timezone(&Local);
+        assert_eq!((due.month(), due.day(), due.hour()), (11, 12, 8));
+
+        assert_eq!(tasks[1].description, "Book venue");
+        assert_eq!(tasks[1].parent, Some(0));
+        assert_eq!(tasks[1].state, TaskState::Done);
+        assert_eq!(tasks[2].parent, Some(0));
+        assert_eq!(tasks[2].state, TaskState::Todo);
+
+        assert_eq!(tasks[3].category.as_deref(), Some("Home"));
+        assert_eq!(tasks[3].state, TaskState::Done);
+        assert_eq!(
+            tasks[3].completed,
+            Some(Utc.ymd(2026, 10, 11).and_hms(0, 0, 0))
+        );
+        assert_eq!(
+            tasks[3].recurrence.as_ref().map(|r| &r.every),
+            Some(&Recurrence::Weekly(vec![Weekday::Sat]))
+        );
+    }
+
+    #[test]
+    fn weekly_intervals_count_in_days() {
+        let weekly = |interval| RecurrencePattern {
+            kind: String::from("weekly"),
+            interval,
+            days_of_week: Vec::new(),
+            day_of_month: 0,
+        };
+        assert_eq!(
+            parse_recurrence(&weekly(2)).map(|r| r.every),
+            Some(Recurrence::EveryNDays(14))
+        );
+        assert_eq!(parse_recurrence(&weekly(1_000_000_000)), None);
+    }
+}
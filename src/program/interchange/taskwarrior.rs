@@ -0,0 +1,176 @@
+use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::super::{
+    objects::{Priority, RecurFrom, Recurrence, RecurrenceRule, Task, TaskState},
+    EfficacyResult,
+};
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+#[derive(Deserialize)]
+struct TaskwarriorTask {
+    uuid: String,
+    description: String,
+    status: String,
+    project: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    priority: Option<String>,
+    due: Option<String>,
+    entry: Option<String>,
+    modified: Option<String>,
+    end: Option<String>,
+    recur: Option<String>,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    depends: Option<Depends>,
+}
+
+#[derive(Deserialize)]
+struct Annotation {
+    description: String,
+}
+
+/// Older versions of Taskwarrior write dependencies as one comma separated string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Depends {
+    List(Vec<String>),
+    Joined(String),
+}
+
+/// Reads the output of `task export`. Projects become categories and annotations are joined into
+/// the information. Deleted tasks are skipped, as are the templates of recurring tasks, whose
+/// pending instances are imported instead.
+pub fn import(contents: &str) -> EfficacyResult<Vec<Task>> {
+    let exported: Vec<TaskwarriorTask> =
+        serde_json::from_str(contents).map_err(super::json_error)?;
+
+    let mut tasks = Vec::new();
+    let mut uuids = HashMap::new();
+    let mut dependencies = Vec::new();
+
+    for exported_task in exported {
+        let state = match &exported_task.status[..] {
+            "pending" | "waiting" => TaskState::Todo,
+            "completed" => TaskState::Done,
+            _ => continue,
+        };
+
+        let mut task = Task::new(exported_task.description);
+        task.id = tasks.len();
+        task.state = state;
+        task.category = exported_task.project;
+        task.tags = exported_task.tags.into_iter().collect();
+        task.priority = match exported_task.priority.as_deref() {
+            Some("H") => Priority::High,
+            Some("M") => Priority::Medium,
+            Some("L") => Priority::Low,
+            _ => Priority::None,
+        };
+        task.due = exported_task.due.as_deref().and_then(parse_date);
+        task.created = exported_task.entry.as_deref().and_then(parse_date);
+        task.modified = exported_task.modified.as_deref().and_then(parse_date);
+        if task.state == TaskState::Done {
+            task.completed = exported_task.end.as_deref().and_then(parse_date);
+        }
+        task.recurrence = exported_task
+            .recur
+            .as_deref()
+            .and_then(|r| parse_recurrence(r, task.due));
+
+        let notes: Vec<String> = exported_task
+            .annotations
+            .into_iter()
+            .map(|a| a.description)
+            .collect();
+        if !notes.is_empty() {
+            task.information = Some(notes.join("\n"));
+        }
+
+        let depends = match exported_task.depends {
+            Some(Depends::List(uuids)) => uuids,
+            Some(Depends::Joined(joined)) => joined.split(',').map(String::from).collect(),
+            None => Vec::new(),
+        };
+        for uuid in depends {
+            dependencies.push((task.id, uuid));
+        }
+
+        uuids.insert(exported_task.uuid, task.id);
+        tasks.push(task);
+    }
+
+    for (id, uuid) in dependencies {
+        if let Some(dependency) = uuids.get(uuid.trim()) {
+            tasks[id].depends_on.insert(*dependency);
+        }
+    }
+
+    Ok(tasks)
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    Utc.datetime_from_str(value, DATE_FORMAT).ok()
+}
+
+/// Only the simple periods have a counterpart; tasks with others are imported without repeating.
+fn parse_recurrence(value: &str, due: Option<DateTime<Utc>>) -> Option<RecurrenceRule> {
+    let every = match value {
+        "daily" | "day" | "1d" => Recurrence::Daily,
+        "weekly" | "week" | "1w" => Recurrence::Weekly(Vec::new()),
+        "biweekly" | "fortnight" | "2w" => Recurrence::EveryNDays(14),
+        "monthly" | "month" | "1m" | "1mo" => {
+            let day = due.unwrap_or_else(Utc::now).with_timezone(&Local);
+            Recurrence::Monthly(day.day())
+        }
+        _ => return None,
+    };
+
+    Some(RecurrenceRule {
+        every,
+        from: RecurFrom::Due,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::errors::EfficacyError;
+
+    #[test]
+    fn reads_task_export() {
+        let tasks = import(include_str!("samples/taskwarrior.json")).unwrap();
+        assert_eq!(tasks.len(), 3);
+
+        assert_eq!(tasks[0].description, "Renew passport");
+        assert_eq!(tasks[0].category.as_deref(), Some("Home"));
+        assert_eq!(tasks[0].priority, Priority::High);
+        assert!(tasks[0].has_tag("errand"));
+        assert_eq!(tasks[0].due, Some(Utc.ymd(2026, 11, 20).and_hms(17, 0, 0)));
+        assert_eq!(
+            tasks[0].information.as_deref(),
+            Some("Photos are in the drawer\nCheck the fee")
+        );
+        assert!(tasks[0].depends_on.contains(&1));
+
+        assert_eq!(tasks[1].state, TaskState::Done);
+        assert_eq!(
+            tasks[1].completed,
+            Some(Utc.ymd(2026, 10, 12).and_hms(9, 15, 0))
+        );
+
+        assert_eq!(tasks[2].description, "Water plants");
+        assert_eq!(
+            tasks[2].recurrence.as_ref().map(|r| &r.every),
+            Some(&Recurrence::Weekly(Vec::new()))
+        );
+
+        match import("[{\"description\": \"No UUID\"}]") {
+            Err(EfficacyError::ImportError { line: 1, .. }) => (),
+            other => panic!("Expected an ImportError, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::super::{
+    objects::{Priority, RecurFrom, Recurrence, RecurrenceRule, Task, TaskState},
+    EfficacyResult,
+};
+
+/// Either the whole account as returned by the sync API, or just a list of tasks.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Export {
+    Items(Vec<Item>),
+    Account {
+        #[serde(default)]
+        projects: Vec<Project>,
+        #[serde(alias = "tasks")]
+        items: Vec<Item>,
+    },
+}
+
+#[derive(Deserialize)]
+struct Project {
+    id: Value,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    id: Value,
+    content: String,
+    #[serde(default)]
+    description: String,
+    project_id: Option<Value>,
+    parent_id: Option<Value>,
+    #[serde(default, alias = "is_completed")]
+    checked: bool,
+    #[serde(default)]
+    is_deleted: bool,
+    /// 4 is the most urgent, shown as p1 in Todoist.
+    #[serde(default = "default_priority")]
+    priority: u8,
+    due: Option<Due>,
+    #[serde(default)]
+    labels: Vec<String>,
+    #[serde(alias = "created_at")]
+    added_at: Option<String>,
+    completed_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Due {
+    date: String,
+    datetime: Option<String>,
+    #[serde(default)]
+    is_recurring: bool,
+    #[serde(default)]
+    string: String,
+}
+
+fn default_priority() -> u8 {
+    1
+}
+
+/// Reads tasks from the Todoist API. Projects become categories, labels become tags and
+/// descriptions become the information. Due dates without a time are given `default_time`.
+pub fn import(contents: &str, default_time: NaiveTime) -> EfficacyResult<Vec<Task>> {
+    let (projects, items) = match serde_json::from_str(contents).map_err(super::json_error)? {
+        Export::Account { projects, items } => (projects, items),
+        Export::Items(items) => (Vec::new(), items),
+    };
+
+    let project_names: HashMap<String, String> = projects
+        .into_iter()
+        .map(|p| (id_key(&p.id), p.name))
+        .collect();
+
+    let mut tasks = Vec::new();
+    let mut ids = HashMap::new();
+    let mut parents = Vec::new();
+
+    for item in items.into_iter().filter(|i| !i.is_deleted) {
+        let mut task = Task::new(item.content);
+        task.id = tasks.len();
+        task.category = item
+            .project_id
+            .and_then(|p| project_names.get(&id_key(&p)).cloned());
+        task.tags = item.labels.into_iter().collect();
+        task.priority = match item.priority {
+            4 => Priority::High,
+            3 => Priority::Medium,
+            2 => Priority::Low,
+            _ => Priority::None,
+        };
+        if !item.description.is_empty() {
+            task.information = Some(item.description);
+        }
+        if item.checked {
+            task.state = TaskState::Done;
+            task.completed = item.completed_at.as_deref().and_then(parse_time);
+        }
+        task.created = item.added_at.as_deref().and_then(parse_time);
+
+        if let Some(due) = item.due {
+            task.due = match &due.datetime {
+                Some(datetime) => parse_time(datetime),
+                None => parse_time(&due.date).or_else(|| {
+                    NaiveDate::parse_from_str(&due.date, "%Y-%m-%d")
+                        .ok()
+                        .map(|d| super::local_to_utc(d.and_time(default_time)))
+                }),
+            };
+            if due.is_recurring {
+                task.recurrence = parse_recurrence(&due.string, task.due);
+            }
+        }
+
+        if let Some(parent) = item.parent_id {
+            parents.push((task.id, id_key(&parent)));
+        }
+        ids.insert(id_key(&item.id), task.id);
+        tasks.push(task);
+    }
+
+    for (id, parent) in parents {
+        tasks[id].parent = ids.get(&parent).cloned();
+    }
+
+    Ok(tasks)
+}
+
+/// IDs are numbers in older exports and strings in newer ones.
+fn id_key(id: &Value) -> String {
+    match id {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Times ending in `Z` or an offset are fixed, others are in local time.
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(super::local_to_utc)
+}
+
+/// Only the plainest of Todoist's recurring due dates have a counterpart.
+fn parse_recurrence(value: &str, due: Option<DateTime<Utc>>) -> Option<RecurrenceRule> {
+    let every = match &value.to_lowercase()[..] {
+        "every day" | "daily" => Recurrence::Daily,
+        "every week" | "weekly" => Recurrence::Weekly(Vec::new()),
+        "every month" | "monthly" => {
+            let day = due.unwrap_or_else(Utc::now).with_timezone(&Local);
+            Recurrence::Monthly(day.day())
+        }
+        _ => return None,
+    };
+
+    Some(RecurrenceRule {
+        every,
+        from: RecurFrom::Due,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Timelike};
+
+    #[test]
+    fn reads_todoist_export() {
+        let default_time = NaiveTime::from_hms(8, 0, 0);
+        let tasks = import(include_str!("samples/todoist.json"), default_time).unwrap();
+        assert_eq!(tasks.len(), 3);
+
+        assert_eq!(tasks[0].description, "Prepare quarterly review");
+        assert_eq!(tasks[0].category.as_deref(), Some("Work"));
+        assert_eq!(tasks[0].priority, Priority::High);
+        assert_eq!(
+            tasks[0].information.as_deref(),
+            Some("Numbers are in the shared drive")
+        );
+        assert!(tasks[0].has_tag("review"));
+        assert_eq!(tasks[0].due, Some(Utc.ymd(2026, 10, 30).and_hms(16, 0, 0)));
+
+        assert_eq!(tasks[1].parent, Some(0));
+        assert_eq!(tasks[1].state, TaskState::Done);
+        assert_eq!(
+            tasks[1].completed,
+            Some(Utc.ymd(2026, 10, 14).and_hms(11, 0, 0))
+        );
+
+        assert_eq!(tasks[2].category.as_deref(), Some("Inbox"));
+        let due = tasks[2].due.unwrap().with_timezone(&Local);
+        assert_eq!((due.day(), due.hour()), (20, 8));
+        assert_eq!(
+            tasks[2].recurrence.as_ref().map(|r| &r.every),
+            Some(&Recurrence::Daily)
+        );
+
+        let rest = r#"[{"id": "1", "content": "Call mum", "is_completed": true, "priority": 2}]"#;
+        let tasks = import(rest, default_time).unwrap();
+        assert_eq!(tasks[0].state, TaskState::Done);
+        assert_eq!(tasks[0].priority, Priority::Low);
+    }
+}
@@ -0,0 +1,289 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+
+use super::super::{
+    errors::EfficacyError,
+    objects::{Priority, RecurFrom, Recurrence, RecurrenceRule, Task, TaskState},
+    EfficacyResult,
+};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Writes one task per line. Done tasks start with `x` and their completion date, priorities
+/// become `(A)` to `(C)`, the category becomes a `+project` and tags become `@contexts`. Due
+/// dates and recurrences are written as `due:` and `rec:`, and the priority of done tasks as
+/// `pri:`, since todo.txt drops it from completed lines.
+pub fn export(tasks: &[Task]) -> String {
+    let mut lines = Vec::new();
+
+    for task in tasks {
+        let mut words: Vec<String> = Vec::new();
+
+        if task.state == TaskState::Done {
+            words.push(String::from("x"));
+            if let Some(completed) = task.completed {
+                words.push(local_date(completed));
+            }
+        } else if let Some(letter) = priority_letter(task.priority) {
+            words.push(format!("({})", letter));
+        }
+
+        if let Some(created) = task.created {
+            // A creation date can only follow a completion date on done lines
+            if task.state == TaskState::Todo || task.completed.is_some() {
+                words.push(local_date(created));
+            }
+        }
+
+        words.push(task.description.clone());
+
+        if let Some(category) = &task.category {
+            words.push(format!("+{}", without_whitespace(category)));
+        }
+        for tag in &task.tags {
+            words.push(format!("@{}", without_whitespace(tag)));
+        }
+        if let Some(due) = task.due {
+            words.push(format!("due:{}", local_date(due)));
+        }
+        if let Some(rule) = &task.recurrence {
+            words.push(format!("rec:{}", recurrence_value(rule)));
+        }
+        if task.state == TaskState::Done {
+            if let Some(letter) = priority_letter(task.priority) {
+                words.push(format!("pri:{}", letter));
+            }
+        }
+
+        lines.push(words.join(" "));
+    }
+
+    let mut result = lines.join("\n");
+    result.push('\n');
+    result
+}
+
+/// Reads one task per line, skipping blank lines. The first `+project` becomes the category and
+/// any others become tags, as do `@contexts`. `due:`, `rec:` and `pri:` are understood; other
+/// `key:value` pairs, and `due:` without a date, stay in the description.
+pub fn import(contents: &str, default_time: NaiveTime) -> EfficacyResult<Vec<Task>> {
+    let mut tasks = Vec::new();
+
+    for (index, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut task =
+            parse_line(line, default_time).map_err(|message| EfficacyError::ImportError {
+                line: index + 1,
+                message,
+            })?;
+        task.id = tasks.len();
+        tasks.push(task);
+    }
+
+    Ok(tasks)
+}
+
+fn parse_line(line: &str, default_time: NaiveTime) -> Result<Task, String> {
+    let mut words = line.split_whitespace().peekable();
+    let mut task = Task::new(String::new());
+
+    if words.peek() == Some(&"x") {
+        words.next();
+        task.state = TaskState::Done;
+        if let Some(date) = words.peek().and_then(|w| parse_date(w)) {
+            words.next();
+            task.completed = Some(at_time(date, NaiveTime::from_hms(0, 0, 0)));
+        }
+    } else if let Some(priority) = words.peek().and_then(|w| parse_priority_word(w)) {
+        words.next();
+        task.priority = priority;
+    }
+
+    if let Some(date) = words.peek().and_then(|w| parse_date(w)) {
+        words.next();
+        task.created = Some(at_time(date, NaiveTime::from_hms(0, 0, 0)));
+    }
+
+    let mut description = Vec::new();
+    let mut recurrence = None;
+
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            if task.category.is_none() {
+                task.category = Some(String::from(project));
+            } else {
+                task.tags.insert(String::from(project));
+            }
+        } else if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            task.tags.insert(String::from(context));
+        } else if let Some(date) = word.strip_prefix("due:").and_then(parse_date) {
+            task.due = Some(at_time(date, default_time));
+        } else if let Some(rec) = word.strip_prefix("rec:") {
+            recurrence = Some(rec);
+        } else if let Some(letter) = word.strip_prefix("pri:") {
+            task.priority = match letter_priority(letter) {
+                Some(p) => p,
+                None => return Err(format!("'{}' is not a priority", letter)),
+            };
+        } else {
+            description.push(word);
+        }
+    }
+
+    if description.is_empty() {
+        return Err(String::from("The task has no description"));
+    }
+    task.description = description.join(" ");
+
+    if let Some(rec) = recurrence {
+        task.recurrence = match parse_recurrence(rec, &task) {
+            Some(r) => Some(r),
+            None => return Err(format!("'{}' is not a recurrence", rec)),
+        };
+    }
+
+    Ok(task)
+}
+
+fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::High => Some('A'),
+        Priority::Medium => Some('B'),
+        Priority::Low => Some('C'),
+        Priority::None => None,
+    }
+}
+
+/// Priorities below `(C)` are all low.
+fn letter_priority(letter: &str) -> Option<Priority> {
+    match letter {
+        "A" => Some(Priority::High),
+        "B" => Some(Priority::Medium),
+        l if l.len() == 1 && ("C"..="Z").contains(&l) => Some(Priority::Low),
+        _ => None,
+    }
+}
+
+fn parse_priority_word(word: &str) -> Option<Priority> {
+    word.strip_prefix('(')
+        .and_then(|w| w.strip_suffix(')'))
+        .and_then(letter_priority)
+}
+
+/// `rec:` counts from completion unless it starts with `+`, in which case it counts from the
+/// due date.
+fn recurrence_value(rule: &RecurrenceRule) -> String {
+    let strict = match rule.from {
+        RecurFrom::Due => "+",
+        RecurFrom::Completion => "",
+    };
+    let interval = match &rule.every {
+        Recurrence::Daily => String::from("1d"),
+        Recurrence::EveryNDays(n) => format!("{}d", n),
+        Recurrence::Weekly(_) => String::from("1w"),
+        Recurrence::Monthly(_) => String::from("1m"),
+    };
+    format!("{}{}", strict, interval)
+}
+
+fn parse_recurrence(value: &str, task: &Task) -> Option<RecurrenceRule> {
+    let (from, interval) = match value.strip_prefix('+') {
+        Some(interval) => (RecurFrom::Due, interval),
+        None => (RecurFrom::Completion, value),
+    };
+    if interval.len() < 2 || !interval.is_ascii() {
+        return None;
+    }
+
+    let (amount, unit) = interval.split_at(interval.len() - 1);
+    let amount: u32 = amount.parse().ok().filter(|a| *a >= 1)?;
+
+    let every = match (unit, amount) {
+        ("d", 1) => Recurrence::Daily,
+        ("d", n) => Recurrence::EveryNDays(n),
+        ("w", 1) => Recurrence::Weekly(Vec::new()),
+        ("w", n) => Recurrence::EveryNDays(n.checked_mul(7)?),
+        ("m", 1) => {
+            let day = task.due.unwrap_or_else(Utc::now).with_timezone(&Local);
+            Recurrence::Monthly(day.day())
+        }
+        _ => return None,
+    };
+
+    Some(RecurrenceRule { every, from })
+}
+
+fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, DATE_FORMAT).ok()
+}
+
+fn local_date(date: DateTime<Utc>) -> String {
+    date.with_timezone(&Local).format(DATE_FORMAT).to_string()
+}
+
+fn at_time(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    match Local.from_local_datetime(&date.and_time(time)).earliest() {
+        Some(d) => d.with_timezone(&Utc),
+        None => Utc.from_utc_datetime(&date.and_time(time)),
+    }
+}
+
+fn without_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_todo_txt() {
+        let contents = "\
+(A) 2026-10-01 Call the bank +Finances @phone due:2026-10-20
+x 2026-10-05 2026-10-01 Water plants +Home rec:+1w pri:B
+
+2026-09-30 Read about due:dates http://example.com t:2026-10-01
+";
+        let tasks = import(contents, NaiveTime::from_hms(8, 0, 0)).unwrap();
+        assert_eq!(tasks.len(), 3);
+
+        assert_eq!(tasks[0].id, 0);
+        assert_eq!(tasks[0].description, "Call the bank");
+        assert_eq!(tasks[0].priority, Priority::High);
+        assert_eq!(tasks[0].category.as_deref(), Some("Finances"));
+        assert!(tasks[0].has_tag("phone"));
+        assert_eq!(local_date(tasks[0].due.unwrap()), "2026-10-20");
+
+        assert_eq!(tasks[1].state, TaskState::Done);
+        assert_eq!(tasks[1].priority, Priority::Medium);
+        assert_eq!(local_date(tasks[1].completed.unwrap()), "2026-10-05");
+        assert_eq!(
+            tasks[1].recurrence,
+            Some(RecurrenceRule {
+                every: Recurrence::Weekly(Vec::new()),
+                from: RecurFrom::Due
+            })
+        );
+
+        assert_eq!(
+            tasks[2].description,
+            "Read about due:dates http://example.com t:2026-10-01"
+        );
+        assert_eq!(tasks[2].due, None);
+
+        let first_two = contents.lines().take(2).collect::<Vec<&str>>().join("\n") + "\n";
+        assert_eq!(export(&tasks[..2]), first_two);
+
+        for bad in &["rec:sometimes", "rec:1000000000w"] {
+            match import(
+                &format!("\n(B) Something {}", bad),
+                NaiveTime::from_hms(8, 0, 0),
+            ) {
+                Err(EfficacyError::ImportError { line: 2, .. }) => (),
+                other => panic!("Expected an ImportError, got {:?}", other),
+            }
+        }
+    }
+}
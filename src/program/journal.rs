@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{
+    objects::Task,
+    trash::{Trash, TrashEntry},
+};
+
+/// How many changes are remembered for undoing before the oldest ones are dropped.
+const MAX_JOURNAL_ENTRIES: usize = 100;
+
+/// A single change to the tasks of one context. `before` holds the changed tasks as they were
+/// and `after` holds them as they became, so swapping the two reverses the change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub context: String,
+    pub operation: String,
+    pub before: Vec<Task>,
+    pub after: Vec<Task>,
+    /// The same for archived tasks, when the change moved tasks in or out of the archive.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archive_before: Vec<Task>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub archive_after: Vec<Task>,
+    /// Trash entries the change took out of the trash, and the ones it put in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash_before: Vec<TrashEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trash_after: Vec<TrashEntry>,
+}
+
+impl JournalEntry {
+    /// Records the difference between two versions of a context's tasks. Returns `None` when
+    /// nothing changed.
+    pub fn between(
+        context: &str,
+        operation: &str,
+        old_tasks: &[Task],
+        new_tasks: &[Task],
+    ) -> Option<JournalEntry> {
+        let (before, after) = differences(old_tasks, new_tasks);
+
+        if before.is_empty() && after.is_empty() {
+            return None;
+        }
+
+        Some(JournalEntry {
+            context: String::from(context),
+            operation: String::from(operation),
+            before,
+            after,
+            archive_before: Vec::new(),
+            archive_after: Vec::new(),
+            trash_before: Vec::new(),
+            trash_after: Vec::new(),
+        })
+    }
+
+    /// Also records the difference between two versions of the context's archive.
+    pub fn with_archive(mut self, old_archive: &[Task], new_archive: &[Task]) -> JournalEntry {
+        let (before, after) = differences(old_archive, new_archive);
+        self.archive_before = before;
+        self.archive_after = after;
+        self
+    }
+
+    /// Also records the trash entries the change took out and put in, so they can follow the
+    /// tasks when it is undone.
+    pub fn with_trash(mut self, taken: Vec<TrashEntry>, added: Vec<TrashEntry>) -> JournalEntry {
+        self.trash_before = taken;
+        self.trash_after = added;
+        self
+    }
+
+    pub fn changes_archive(&self) -> bool {
+        !self.archive_before.is_empty() || !self.archive_after.is_empty()
+    }
+
+    pub fn changes_trash(&self) -> bool {
+        !self.trash_before.is_empty() || !self.trash_after.is_empty()
+    }
+
+    /// Puts the tasks back the way they were before this change.
+    pub fn revert(&self, tasks: &mut Vec<Task>) {
+        swap_versions(tasks, &self.after, &self.before);
+    }
+
+    /// Makes this change again after it was reverted.
+    pub fn reapply(&self, tasks: &mut Vec<Task>) {
+        swap_versions(tasks, &self.before, &self.after);
+    }
+
+    pub fn revert_archive(&self, archive: &mut Vec<Task>) {
+        swap_versions(archive, &self.archive_after, &self.archive_before);
+    }
+
+    pub fn reapply_archive(&self, archive: &mut Vec<Task>) {
+        swap_versions(archive, &self.archive_before, &self.archive_after);
+    }
+
+    pub fn revert_trash(&self, trash: &mut Trash) {
+        trash.swap_entries(&self.trash_after, &self.trash_before);
+    }
+
+    pub fn reapply_trash(&self, trash: &mut Trash) {
+        trash.swap_entries(&self.trash_before, &self.trash_after);
+    }
+
+    /// Whether the change moved any of the given trash entries in or out of the trash.
+    fn moves_trash_entries(&self, ids: &[usize]) -> bool {
+        self.trash_before
+            .iter()
+            .chain(self.trash_after.iter())
+            .any(|e| ids.contains(&e.id))
+    }
+}
+
+/// The tasks only in `old_tasks` and the tasks only in `new_tasks`.
+fn differences(old_tasks: &[Task], new_tasks: &[Task]) -> (Vec<Task>, Vec<Task>) {
+    let before = old_tasks
+        .iter()
+        .filter(|old| !new_tasks.contains(old))
+        .cloned()
+        .collect();
+    let after = new_tasks
+        .iter()
+        .filter(|new| !old_tasks.contains(new))
+        .cloned()
+        .collect();
+
+    (before, after)
+}
+
+fn swap_versions(tasks: &mut Vec<Task>, remove: &[Task], restore: &[Task]) {
+    tasks.retain(|t| {
+        !remove.iter().any(|r| r.id == t.id) && !restore.iter().any(|r| r.id == t.id)
+    });
+    tasks.extend(restore.iter().cloned());
+    tasks.sort_by_key(|t| t.id);
+}
+
+/// Undo and redo stacks for every context, most recent change last.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Journal {
+    pub undo: Vec<JournalEntry>,
+    pub redo: Vec<JournalEntry>,
+}
+
+impl Journal {
+    /// Remembers a new change. Anything that was undone in the same context can no longer be
+    /// redone.
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.redo.retain(|e| e.context != entry.context);
+        self.undo.push(entry);
+
+        if self.undo.len() > MAX_JOURNAL_ENTRIES {
+            let excess = self.undo.len() - MAX_JOURNAL_ENTRIES;
+            self.undo.drain(..excess);
+        }
+    }
+
+    pub fn pop_undo(&mut self, context: &str) -> Option<JournalEntry> {
+        pop_latest(&mut self.undo, context)
+    }
+
+    pub fn pop_redo(&mut self, context: &str) -> Option<JournalEntry> {
+        pop_latest(&mut self.redo, context)
+    }
+
+    /// Forgets every change made in a context, for when the context itself is deleted.
+    pub fn forget_context(&mut self, context: &str) {
+        self.undo.retain(|e| e.context != context);
+        self.redo.retain(|e| e.context != context);
+    }
+
+    /// Forgets the changes that moved any of the given trash entries, for when they have been
+    /// purged from the trash and can't be brought back. The older changes of the same context
+    /// can only be undone or redone after those, so they are forgotten too.
+    pub fn forget_trash_entries(&mut self, ids: &[usize]) {
+        forget_through(&mut self.undo, ids);
+        forget_through(&mut self.redo, ids);
+    }
+}
+
+fn forget_through(entries: &mut Vec<JournalEntry>, trash_ids: &[usize]) {
+    let mut forgotten = HashSet::new();
+    let mut kept: Vec<JournalEntry> = entries
+        .drain(..)
+        .rev()
+        .filter(|e| {
+            if forgotten.contains(&e.context) || e.moves_trash_entries(trash_ids) {
+                forgotten.insert(e.context.clone());
+                return false;
+            }
+            true
+        })
+        .collect();
+    kept.reverse();
+    *entries = kept;
+}
+
+fn pop_latest(entries: &mut Vec<JournalEntry>, context: &str) -> Option<JournalEntry> {
+    let index = entries.iter().rposition(|e| e.context == context)?;
+    Some(entries.remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revert_and_reapply() {
+        let mut first = Task::new(String::from("Write report"));
+        first.id = 0;
+        let mut second = Task::new(String::from("Send report"));
+        second.id = 1;
+
+        let old_tasks = vec![first.clone(), second.clone()];
+        let mut edited = first.clone();
+        edited.description = String::from("Write the report");
+        let new_tasks = vec![edited];
+
+        let entry = JournalEntry::between("default", "edit", &old_tasks, &new_tasks).unwrap();
+        assert_eq!(entry.before.len(), 2);
+        assert_eq!(entry.after.len(), 1);
+
+        let mut tasks = new_tasks.clone();
+        entry.revert(&mut tasks);
+        assert_eq!(tasks, old_tasks);
+
+        entry.reapply(&mut tasks);
+        assert_eq!(tasks, new_tasks);
+
+        assert!(JournalEntry::between("default", "edit", &old_tasks, &old_tasks).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{errors::EfficacyError, objects::Task, EfficacyResult};
+
+/// Version of the task file layout written by this build.
+pub const CURRENT_VERSION: u64 = 2;
+
+/// Upgrades, in order, starting from version 1. The function at index `i` takes a file from
+/// version `i + 1` to version `i + 2`.
+const MIGRATIONS: [fn(Value) -> Value; 1] = [wrap_task_array];
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    tasks: &'a [Task],
+}
+
+#[derive(Deserialize)]
+struct OwnedEnvelope {
+    tasks: Vec<Task>,
+}
+
+pub fn serialize_tasks(tasks: &[Task]) -> serde_json::Result<String> {
+    serde_json::to_string(&Envelope {
+        version: CURRENT_VERSION,
+        tasks,
+    })
+}
+
+/// Reads a task file of any known version, returning its tasks and the version it was stored
+/// as. Empty files are treated as holding no tasks.
+pub fn deserialize_tasks(contents: &str) -> EfficacyResult<(Vec<Task>, u64)> {
+    if contents.trim().is_empty() {
+        return Ok((Vec::new(), CURRENT_VERSION));
+    }
+
+    let mut value: Value = serde_json::from_str(contents)?;
+    let original_version = version_of(&value);
+
+    if original_version > CURRENT_VERSION {
+        return Err(EfficacyError::UnsupportedVersionError(original_version));
+    }
+    // No version before 1 was ever written, so the file has been damaged
+    if original_version < 1 {
+        return Err(EfficacyError::SerdeJsonError(serde::de::Error::custom(
+            "versions start at 1",
+        )));
+    }
+
+    for migration in MIGRATIONS.iter().skip(original_version as usize - 1) {
+        value = migration(value);
+    }
+
+    let envelope: OwnedEnvelope = serde_json::from_value(value)?;
+
+    Ok((envelope.tasks, original_version))
+}
+
+fn version_of(value: &Value) -> u64 {
+    match value {
+        Value::Object(map) => map.get("version").and_then(Value::as_u64).unwrap_or(1),
+        _ => 1,
+    }
+}
+
+/// Version 1 files were a bare array of tasks.
+fn wrap_task_array(value: Value) -> Value {
+    serde_json::json!({
+        "version": 2,
+        "tasks": value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_bare_arrays() {
+        let version_1 = r#"[{"description":"Workout","state":"Todo","category":null,"information":null,"due":null}]"#;
+
+        let (tasks, version) = deserialize_tasks(version_1).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].description, "Workout");
+
+        let (round_tripped, version) = deserialize_tasks(&serialize_tasks(&tasks).unwrap()).unwrap();
+        assert_eq!(version, CURRENT_VERSION);
+        assert_eq!(round_tripped, tasks);
+
+        match deserialize_tasks(r#"{"version":99,"tasks":[]}"#) {
+            Err(EfficacyError::UnsupportedVersionError(99)) => (),
+            other => panic!("Expected an UnsupportedVersionError, got {:?}", other),
+        }
+        match deserialize_tasks(r#"{"version":0,"tasks":[]}"#) {
+            Err(EfficacyError::SerdeJsonError(_)) => (),
+            other => panic!("Expected version 0 to be refused, got {:?}", other),
+        }
+    }
+}
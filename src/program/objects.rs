@@ -1,5 +1,16 @@
-use chrono::{DateTime, Utc};
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// Placeholder ID given to tasks read from data files written before tasks carried their own ID.
+pub const UNASSIGNED_ID: usize = usize::MAX;
+
+fn unassigned_id() -> usize {
+    UNASSIGNED_ID
+}
 
 #[derive(Serialize, Deserialize, Debug, Ord, Eq, PartialEq, PartialOrd, Clone)]
 pub enum TaskState {
@@ -7,16 +18,460 @@ pub enum TaskState {
     Done,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Ordered from most to least urgent, so sorting puts high priority tasks first.
+#[derive(Serialize, Deserialize, Debug, Default, Ord, Eq, PartialEq, PartialOrd, Clone, Copy)]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+    #[default]
+    None,
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Priority::High => "high",
+            Priority::Medium => "medium",
+            Priority::Low => "low",
+            Priority::None => "none",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "h" | "high" => Ok(Priority::High),
+            "m" | "med" | "medium" => Ok(Priority::Medium),
+            "l" | "low" => Ok(Priority::Low),
+            "n" | "none" => Ok(Priority::None),
+            _ => Err(format!("'{}' is not a priority", s)),
+        }
+    }
+}
+
+/// What tasks are ordered by within a group when listing. Open tasks always come first.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum SortKey {
+    Due,
+    Created,
+    #[default]
+    Priority,
+    Description,
+    Id,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "due" => Ok(SortKey::Due),
+            "created" => Ok(SortKey::Created),
+            "priority" => Ok(SortKey::Priority),
+            "description" => Ok(SortKey::Description),
+            "id" => Ok(SortKey::Id),
+            _ => Err(format!("'{}' is not something tasks can be sorted by", s)),
+        }
+    }
+}
+
+/// How tasks are split into groups when listing.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum GroupBy {
+    #[default]
+    Category,
+    /// Overdue, today, this week and later.
+    Due,
+    State,
+    /// Every context, not just the current one.
+    Context,
+    None,
+}
+
+impl std::str::FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "category" => Ok(GroupBy::Category),
+            "due" => Ok(GroupBy::Due),
+            "state" => Ok(GroupBy::State),
+            "context" => Ok(GroupBy::Context),
+            "none" => Ok(GroupBy::None),
+            _ => Err(format!("'{}' is not something tasks can be grouped by", s)),
+        }
+    }
+}
+
+/// How listings are printed.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// A single JSON document.
+    Json,
+    /// One JSON object per line.
+    Ndjson,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("'{}' is not an output format", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum Recurrence {
+    Daily,
+    /// Repeats on each of the given weekdays, or a week later if none are given.
+    Weekly(Vec<Weekday>),
+    /// Repeats on the given day of the month, or the last day of shorter months.
+    Monthly(u32),
+    EveryNDays(u32),
+}
+
+impl Recurrence {
+    /// The first occurrence strictly after `base`, keeping its time of day. `None` if it would
+    /// be past the last date chrono can represent.
+    fn after(&self, base: NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Recurrence::Daily => base.checked_add_signed(Duration::days(1)),
+            Recurrence::EveryNDays(n) => base.checked_add_signed(Duration::days(i64::from(*n))),
+            Recurrence::Weekly(days) => (1..=7)
+                .filter_map(|offset| base.checked_add_signed(Duration::days(offset)))
+                .find(|candidate| days.contains(&candidate.weekday()))
+                .or_else(|| base.checked_add_signed(Duration::weeks(1))),
+            Recurrence::Monthly(day) => {
+                let (mut year, mut month) = (base.year(), base.month());
+                loop {
+                    let day = std::cmp::min(*day, days_in_month(year, month));
+                    let candidate = NaiveDate::from_ymd_opt(year, month, day)?.and_time(base.time());
+                    if candidate > base {
+                        return Some(candidate);
+                    }
+
+                    if month == 12 {
+                        year += 1;
+                        month = 1;
+                    } else {
+                        month += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Number of days in the given month of the given year.
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    let first_of_next = if month == 12 {
+        NaiveDate::from_ymd(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(year, month + 1, 1)
+    };
+    first_of_next.pred().day()
+}
+
+/// Parses a time of day such as `17:00`, `17:00:30`, `5pm` or `9:30am`.
+pub fn parse_time_of_day(string: &str) -> Option<NaiveTime> {
+    let lowered = string.trim().to_lowercase();
+
+    let (clock, afternoon) = if let Some(clock) = lowered.strip_suffix("am") {
+        (clock, Some(false))
+    } else if let Some(clock) = lowered.strip_suffix("pm") {
+        (clock, Some(true))
+    } else {
+        (&lowered[..], None)
+    };
+
+    match afternoon {
+        Some(afternoon) => {
+            let mut parts = clock.splitn(2, ':');
+            let hour: u32 = parts.next().unwrap_or("").parse().ok()?;
+            let minute: u32 = match parts.next() {
+                Some(m) => m.parse().ok()?,
+                None => 0,
+            };
+            if !(1..=12).contains(&hour) {
+                return None;
+            }
+            let hour = hour % 12 + if afternoon { 12 } else { 0 };
+            NaiveTime::from_hms_opt(hour, minute, 0)
+        }
+        None => NaiveTime::parse_from_str(clock, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(clock, "%H:%M"))
+            .ok(),
+    }
+}
+
+impl std::fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekly(days) if days.is_empty() => write!(f, "weekly"),
+            Recurrence::Weekly(days) => write!(
+                f,
+                "weekly on {}",
+                days.iter()
+                    .map(|d| format!("{:?}", d))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Recurrence::Monthly(day) => write!(f, "monthly on day {}", day),
+            Recurrence::EveryNDays(n) => write!(f, "every {} days", n),
+        }
+    }
+}
+
+/// What the next occurrence of a recurring task is counted from.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Copy)]
+pub enum RecurFrom {
+    #[default]
+    Due,
+    Completion,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct RecurrenceRule {
+    pub every: Recurrence,
+    #[serde(default)]
+    pub from: RecurFrom,
+}
+
+impl RecurrenceRule {
+    /// Works out when the next occurrence is due. Tasks without a due date always count from
+    /// their completion. `None` if the next occurrence is too far in the future to represent.
+    pub fn next_due(
+        &self,
+        due: Option<DateTime<Utc>>,
+        completed: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let base = match (self.from, due) {
+            (RecurFrom::Due, Some(d)) => d,
+            _ => completed,
+        };
+
+        let next = self.every.after(base.with_timezone(&Local).naive_local())?;
+
+        // Skip forward over times that fall into a daylight saving gap
+        match Local.from_local_datetime(&next).earliest() {
+            Some(d) => Some(d.with_timezone(&Utc)),
+            None => next
+                .checked_add_signed(Duration::hours(1))
+                .and_then(|later| Local.from_local_datetime(&later).earliest())
+                .map(|d| d.with_timezone(&Utc))
+                .or_else(|| base.checked_add_signed(Duration::days(1))),
+        }
+    }
+}
+
+impl std::fmt::Display for RecurrenceRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.from {
+            RecurFrom::Due => write!(f, "{} (from due date)", self.every),
+            RecurFrom::Completion => write!(f, "{} (from completion)", self.every),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Task {
+    #[serde(default = "unassigned_id")]
+    pub id: usize,
     pub description: String,
     pub state: TaskState,
+    #[serde(default)]
+    pub priority: Priority,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    pub information: Option<String>,
+    pub due: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub recurrence: Option<RecurrenceRule>,
+    #[serde(default)]
+    pub parent: Option<usize>,
+    /// IDs of the tasks that have to be done before this one can be started.
+    #[serde(default)]
+    pub depends_on: BTreeSet<usize>,
+    #[serde(default)]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub completed: Option<DateTime<Utc>>,
+    /// Work recorded with `start` and `stop`, oldest first.
+    #[serde(default)]
+    pub time_log: Vec<WorkInterval>,
+}
+
+impl Task {
+    /// A new todo task with nothing but a description. The ID is handed out when it is added.
+    pub fn new(description: String) -> Self {
+        Task {
+            id: UNASSIGNED_ID,
+            description,
+            state: TaskState::Todo,
+            priority: Priority::None,
+            category: None,
+            tags: BTreeSet::new(),
+            information: None,
+            due: None,
+            recurrence: None,
+            parent: None,
+            depends_on: BTreeSet::new(),
+            created: None,
+            modified: None,
+            completed: None,
+            time_log: Vec::new(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// Whether the clock is running on the task.
+    pub fn is_running(&self) -> bool {
+        self.time_log.last().is_some_and(|i| i.end.is_none())
+    }
+
+    /// Stops the clock if it is running on the task.
+    pub fn stop_clock(&mut self, now: DateTime<Utc>) {
+        if let Some(interval) = self.time_log.last_mut() {
+            if interval.end.is_none() {
+                interval.end = Some(now);
+            }
+        }
+    }
+}
+
+/// A stretch of time spent working on a task. `end` is unset while the clock is running.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WorkInterval {
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+/// Changes to apply to an existing task. Anything left unset is kept as it is.
+#[derive(Debug, Default, Clone)]
+pub struct TaskEdit {
+    pub description: Option<String>,
     pub category: Option<String>,
     pub information: Option<String>,
     pub due: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    /// `Some(None)` stops the task from recurring.
+    pub recurrence: Option<Option<Recurrence>>,
+    pub recur_from: Option<RecurFrom>,
+    /// `Some(None)` turns a subtask back into a top level task.
+    pub parent: Option<Option<usize>>,
+    pub add_dependencies: Vec<usize>,
+    pub remove_dependencies: Vec<usize>,
+}
+
+impl TaskEdit {
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none()
+            && self.category.is_none()
+            && self.information.is_none()
+            && self.due.is_none()
+            && self.priority.is_none()
+            && self.add_tags.is_empty()
+            && self.remove_tags.is_empty()
+            && self.recurrence.is_none()
+            && self.recur_from.is_none()
+            && self.parent.is_none()
+            && self.add_dependencies.is_empty()
+            && self.remove_dependencies.is_empty()
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Context {
     pub context_name: String,
+    /// Next task ID to hand out, per context.
+    #[serde(default)]
+    pub next_ids: HashMap<String, usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        Local.ymd(y, m, d).and_hms(9, 0, 0).with_timezone(&Utc)
+    }
+
+    #[test]
+    fn next_due_dates() {
+        let weekly = RecurrenceRule {
+            every: Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]),
+            from: RecurFrom::Due,
+        };
+        // 2026-10-14 is a Wednesday
+        let due = local(2026, 10, 14);
+        assert_eq!(weekly.next_due(Some(due), due), Some(local(2026, 10, 16)));
+
+        let monthly = RecurrenceRule {
+            every: Recurrence::Monthly(31),
+            from: RecurFrom::Due,
+        };
+        let due = local(2026, 1, 31);
+        assert_eq!(monthly.next_due(Some(due), due), Some(local(2026, 2, 28)));
+
+        let every_three = RecurrenceRule {
+            every: Recurrence::EveryNDays(3),
+            from: RecurFrom::Completion,
+        };
+        let completed = local(2026, 10, 20);
+        assert_eq!(
+            every_three.next_due(Some(local(2026, 10, 1)), completed),
+            Some(local(2026, 10, 23))
+        );
+
+        let too_far = RecurrenceRule {
+            every: Recurrence::EveryNDays(u32::MAX),
+            from: RecurFrom::Due,
+        };
+        assert_eq!(too_far.next_due(Some(due), due), None);
+    }
+
+    #[test]
+    fn priorities_parse_and_order() {
+        let parsed: Vec<Priority> = ["h", "Medium", "low", "none"]
+            .iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                Priority::High,
+                Priority::Medium,
+                Priority::Low,
+                Priority::None
+            ]
+        );
+        assert!("urgent".parse::<Priority>().is_err());
+
+        // Most urgent first
+        let mut sorted = parsed.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert_eq!(sorted, parsed);
+    }
 }
@@ -0,0 +1,212 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::objects::{OutputFormat, RecurFrom, Recurrence, Task, TaskState};
+
+/// The machine-readable output of listing commands, selected with `--output json` or
+/// `--output ndjson`.
+///
+/// With `json`, a task listing is a single document `{"version": 1, "tasks": [...]}`, a single
+/// task is `{"version": 1, "task": {...}}`, the context listing is
+/// `{"version": 1, "contexts": [{"name": "default", "current": true}, ...]}`, the trash
+/// listing is `{"version": 1, "trash": [...]}` and the time report is
+/// `{"version": 1, "time": [...]}`. With `ndjson` the same records are written one per line,
+/// without the surrounding document.
+///
+/// Records only ever gain new fields within a version. Removing or changing the meaning of a
+/// field bumps `SCHEMA_VERSION`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A task as it appears in machine-readable output.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TaskRecord {
+    /// Unique within the task's context, and never reused.
+    pub id: usize,
+    pub context: String,
+    pub description: String,
+    /// `"todo"` or `"done"`.
+    pub state: String,
+    /// `"high"`, `"medium"`, `"low"` or `"none"`.
+    pub priority: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub information: Option<String>,
+    /// RFC 3339 timestamps in UTC, as are `created`, `modified` and `completed`.
+    pub due: Option<DateTime<Utc>>,
+    /// In the form taken by `--repeat`: `daily`, `weekly`, `weekly:mon,fri`, `monthly:15` or
+    /// `every:3`.
+    pub recurrence: Option<String>,
+    /// `"due"` or `"done"`, when the task repeats.
+    pub recur_from: Option<String>,
+    pub parent: Option<usize>,
+    pub depends_on: Vec<usize>,
+    /// Whether any task this one depends on is still to do.
+    pub blocked: bool,
+    /// The heading the task was listed under, if the listing was grouped.
+    pub group: Option<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    pub completed: Option<DateTime<Utc>>,
+}
+
+impl TaskRecord {
+    pub fn new(task: &Task, context: &str, group: Option<&str>, blocked: bool) -> TaskRecord {
+        TaskRecord {
+            id: task.id,
+            context: String::from(context),
+            description: task.description.clone(),
+            state: String::from(match task.state {
+                TaskState::Todo => "todo",
+                TaskState::Done => "done",
+            }),
+            priority: task.priority.to_string(),
+            category: task.category.clone(),
+            tags: task.tags.iter().cloned().collect(),
+            information: task.information.clone(),
+            due: task.due,
+            recurrence: task.recurrence.as_ref().map(|r| recurrence_spec(&r.every)),
+            recur_from: task.recurrence.as_ref().map(|r| {
+                String::from(match r.from {
+                    RecurFrom::Due => "due",
+                    RecurFrom::Completion => "done",
+                })
+            }),
+            parent: task.parent,
+            depends_on: task.depends_on.iter().cloned().collect(),
+            blocked,
+            group: group.map(String::from),
+            created: task.created,
+            modified: task.modified,
+            completed: task.completed,
+        }
+    }
+}
+
+/// A context as it appears in machine-readable output.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ContextRecord {
+    pub name: String,
+    pub current: bool,
+}
+
+/// Something deleted into the trash, as it appears in machine-readable output.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TrashRecord {
+    /// What the item is restored by with `trash restore`.
+    pub id: usize,
+    /// Either "task", "category" or "context".
+    pub kind: String,
+    pub name: String,
+    pub context: String,
+    /// How many tasks restoring the item brings back.
+    pub tasks: usize,
+    pub deleted: DateTime<Utc>,
+}
+
+/// Time spent on a task on one day, as it appears in machine-readable output and CSV reports.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TimeRecord {
+    /// The local date, as `YYYY-MM-DD`.
+    pub date: NaiveDate,
+    pub context: String,
+    pub id: usize,
+    pub description: String,
+    pub category: Option<String>,
+    pub hours: f64,
+}
+
+fn recurrence_spec(recurrence: &Recurrence) -> String {
+    match recurrence {
+        Recurrence::Daily => String::from("daily"),
+        Recurrence::Weekly(days) if days.is_empty() => String::from("weekly"),
+        Recurrence::Weekly(days) => format!(
+            "weekly:{}",
+            days.iter()
+                .map(|d| format!("{:?}", d).to_lowercase())
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        Recurrence::Monthly(day) => format!("monthly:{}", day),
+        Recurrence::EveryNDays(n) => format!("every:{}", n),
+    }
+}
+
+/// The document written with `--output json`, holding its records under a single key.
+#[derive(Serialize)]
+struct Document<'a, T: Serialize> {
+    version: u32,
+    #[serde(flatten)]
+    body: BTreeMap<&'a str, T>,
+}
+
+fn document<T: Serialize>(key: &str, body: T) -> String {
+    let mut document = Document {
+        version: SCHEMA_VERSION,
+        body: BTreeMap::new(),
+    };
+    document.body.insert(key, body);
+
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+/// Writes records either as one document with the records under `key`, or one per line.
+pub fn render<T: Serialize>(key: &str, records: &[T], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Ndjson => records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect::<Vec<String>>()
+            .join("\n"),
+        _ => document(key, records),
+    }
+}
+
+/// Writes a single record, as a document with the record under `key` or as one line.
+pub fn render_one<T: Serialize>(key: &str, record: &T, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Ndjson => serde_json::to_string(record).unwrap(),
+        _ => document(key, record),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::objects::RecurrenceRule;
+    use chrono::{TimeZone, Weekday};
+
+    #[test]
+    fn records_follow_the_schema() {
+        let mut task = Task::new(String::from("Water plants"));
+        task.id = 4;
+        task.tags.insert(String::from("home"));
+        task.due = Some(Utc.ymd(2026, 11, 3).and_hms(8, 0, 0));
+        task.recurrence = Some(RecurrenceRule {
+            every: Recurrence::Weekly(vec![Weekday::Mon, Weekday::Fri]),
+            from: RecurFrom::Completion,
+        });
+        task.created = None;
+        task.modified = None;
+
+        let record = TaskRecord::new(&task, "default", Some("Later"), false);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            concat!(
+                r#"{"id":4,"context":"default","description":"Water plants","state":"todo","#,
+                r#""priority":"none","category":null,"tags":["home"],"information":null,"#,
+                r#""due":"2026-11-03T08:00:00Z","recurrence":"weekly:mon,fri","recur_from":"done","#,
+                r#""parent":null,"depends_on":[],"blocked":false,"group":"Later","#,
+                r#""created":null,"modified":null,"completed":null}"#
+            )
+        );
+
+        let rendered = render("tasks", std::slice::from_ref(&record), OutputFormat::Json);
+        let document: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(document["version"], 1);
+        assert_eq!(document["tasks"][0]["id"], 4);
+
+        let lines = render("tasks", &[record.clone(), record], OutputFormat::Ndjson);
+        assert_eq!(lines.lines().count(), 2);
+    }
+}
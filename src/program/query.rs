@@ -0,0 +1,493 @@
+use chrono::{DateTime, Utc};
+
+use super::{
+    errors::EfficacyError,
+    objects::{Priority, Task, TaskState},
+    EfficacyResult,
+};
+
+/// A filter over tasks, such as `category:work due.before:fri state:todo tag:urgent "report"`.
+///
+/// Terms next to each other must all match. `OR`, `NOT` and parentheses combine them otherwise,
+/// with `NOT` binding tightest and `OR` loosest. The operators have to be written in capitals;
+/// anything else without a known `key:` is searched for in the description and information of
+/// tasks, ignoring case. Quotes keep words together, as in `"text search"` or `tag:"two words"`.
+///
+/// The supported terms are `category:<name>` (or `category:none`), `state:todo|done`,
+/// `tag:<tag>`, `priority:<priority>`, `due:none|any`, `due.before:<date>` and
+/// `due.after:<date>`.
+#[derive(Debug, PartialEq)]
+pub enum Query {
+    All,
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Category(Option<String>),
+    State(TaskState),
+    Tag(String),
+    Priority(Priority),
+    HasDue(bool),
+    DueBefore(DateTime<Utc>),
+    DueAfter(DateTime<Utc>),
+    Text(String),
+}
+
+impl Query {
+    /// Parses a filter expression. Dates are handed to `parse_date`, so they can be written in
+    /// whatever forms it understands.
+    pub fn parse<F>(input: &str, parse_date: F) -> EfficacyResult<Query>
+    where
+        F: Fn(&str) -> Option<DateTime<Utc>>,
+    {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(Query::All);
+        }
+
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            parse_date: &parse_date,
+        };
+        let query = parser.parse_or()?;
+
+        match parser.peek() {
+            None => Ok(query),
+            Some(Token::RightParen) => Err(query_error("Unmatched ')'")),
+            Some(token) => Err(query_error(&format!("Unexpected '{}'", token))),
+        }
+    }
+
+    /// Matches tasks carrying every tag in `tags` and none of the tags in `without_tags`.
+    pub fn tagged(tags: &[String], without_tags: &[String]) -> Query {
+        let with = tags.iter().map(|t| Query::Tag(t.clone()));
+        let without = without_tags
+            .iter()
+            .map(|t| Query::Not(Box::new(Query::Tag(t.clone()))));
+
+        with.chain(without).fold(Query::All, Query::and)
+    }
+
+    /// Both queries, skipping `All` so it doesn't need to be evaluated.
+    pub fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, q) | (q, Query::All) => q,
+            (a, b) => Query::And(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn matches(&self, task: &Task) -> bool {
+        match self {
+            Query::All => true,
+            Query::And(a, b) => a.matches(task) && b.matches(task),
+            Query::Or(a, b) => a.matches(task) || b.matches(task),
+            Query::Not(q) => !q.matches(task),
+            Query::Category(None) => task.category.is_none(),
+            Query::Category(Some(category)) => match &task.category {
+                Some(c) => c.to_lowercase() == category.to_lowercase(),
+                None => false,
+            },
+            Query::State(state) => task.state == *state,
+            Query::Tag(tag) => task.has_tag(tag),
+            Query::Priority(priority) => task.priority == *priority,
+            Query::HasDue(has_due) => task.due.is_some() == *has_due,
+            Query::DueBefore(date) => task.due.is_some_and(|d| d < *date),
+            Query::DueAfter(date) => task.due.is_some_and(|d| d > *date),
+            Query::Text(text) => {
+                let text = text.to_lowercase();
+                task.description.to_lowercase().contains(&text)
+                    || task
+                        .information
+                        .as_ref()
+                        .is_some_and(|i| i.to_lowercase().contains(&text))
+            }
+        }
+    }
+}
+
+/// The tasks a command acts on, given either as IDs and ranges such as `3 5 7-9`, or as a
+/// filter such as `category:errands`. IDs are kept as ranges from first to last, which run
+/// backwards for `9-7`; a single ID is a range of one.
+#[derive(Debug, PartialEq)]
+pub enum Selection {
+    Ids(Vec<(usize, usize)>),
+    Matching(Query),
+}
+
+impl Selection {
+    /// Reads the words as IDs if every one of them is an ID or a range, and as a filter
+    /// otherwise. A word starting with a digit has to be an ID or a range, so a mistyped list
+    /// such as `1,3` or `4-` is an error instead of a search for tasks mentioning it. Text
+    /// starting with a digit can still be searched for in quotes.
+    pub fn parse<F>(words: &[String], parse_date: F) -> EfficacyResult<Selection>
+    where
+        F: Fn(&str) -> Option<DateTime<Utc>>,
+    {
+        let ranges: Option<Vec<(usize, usize)>> = words.iter().map(|w| parse_id_range(w)).collect();
+        if let Some(ranges) = ranges.filter(|r| !r.is_empty()) {
+            return Ok(Selection::Ids(ranges));
+        }
+
+        if let Some(word) = words
+            .iter()
+            .find(|w| w.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            return Err(match parse_id_range(word) {
+                Some(_) => query_error("IDs can't be combined with a filter"),
+                None => query_error(&format!(
+                    "'{}' is not an ID or a range of IDs such as 7-9",
+                    word
+                )),
+            });
+        }
+
+        Ok(Selection::Matching(Query::parse(
+            &words.join(" "),
+            parse_date,
+        )?))
+    }
+}
+
+/// Every ID of the ranges, in the order given and without repeats, or `None` if a range covers
+/// more than `limit` IDs. Lengths are checked before anything is listed, so `1-4000000000`
+/// doesn't have to be.
+pub fn expand_ids(ranges: &[(usize, usize)], limit: usize) -> Option<Vec<usize>> {
+    if ranges
+        .iter()
+        .any(|(first, last)| first.abs_diff(*last) >= limit)
+    {
+        return None;
+    }
+
+    let mut ids = Vec::new();
+    for &(first, last) in ranges {
+        let range: Box<dyn Iterator<Item = usize>> = if first <= last {
+            Box::new(first..=last)
+        } else {
+            Box::new((last..=first).rev())
+        };
+        for id in range {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Some(ids)
+}
+
+/// A single ID such as `7`, or a range such as `7-9`.
+fn parse_id_range(word: &str) -> Option<(usize, usize)> {
+    if let Ok(id) = word.parse::<usize>() {
+        return Some((id, id));
+    }
+
+    let mut bounds = word.splitn(2, '-');
+    let first: usize = bounds.next()?.parse().ok()?;
+    let last: usize = bounds.next()?.parse().ok()?;
+
+    Some((first, last))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    LeftParen,
+    RightParen,
+    /// A word and whether it was quoted as a whole, which makes it plain text.
+    Word(String, bool),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::Word(word, _) => write!(f, "{}", word),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> EfficacyResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+            tokens.push(Token::LeftParen);
+        } else if c == ')' {
+            chars.next();
+            tokens.push(Token::RightParen);
+        } else {
+            let quoted = c == '"';
+            let mut word = String::new();
+            let mut in_quotes = false;
+
+            while let Some(&c) = chars.peek() {
+                if c == '"' {
+                    in_quotes = !in_quotes;
+                } else if !in_quotes && (c.is_whitespace() || c == '(' || c == ')') {
+                    break;
+                } else {
+                    word.push(c);
+                }
+                chars.next();
+            }
+
+            if in_quotes {
+                return Err(query_error("Unterminated quote"));
+            }
+            tokens.push(Token::Word(word, quoted));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    parse_date: &'a dyn Fn(&str) -> Option<DateTime<Utc>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_operator(&self, operator: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word, false)) => word == operator,
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> EfficacyResult<Query> {
+        let mut query = self.parse_and()?;
+
+        while self.peek_operator("OR") {
+            self.position += 1;
+            query = Query::Or(Box::new(query), Box::new(self.parse_and()?));
+        }
+
+        Ok(query)
+    }
+
+    fn parse_and(&mut self) -> EfficacyResult<Query> {
+        let mut query = self.parse_not()?;
+
+        loop {
+            if self.peek_operator("AND") {
+                self.position += 1;
+            } else if self.peek_operator("OR") {
+                break;
+            } else {
+                match self.peek() {
+                    None | Some(Token::RightParen) => break,
+                    _ => (),
+                }
+            }
+            query = Query::And(Box::new(query), Box::new(self.parse_not()?));
+        }
+
+        Ok(query)
+    }
+
+    fn parse_not(&mut self) -> EfficacyResult<Query> {
+        if self.peek_operator("NOT") {
+            self.position += 1;
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_term()
+    }
+
+    fn parse_term(&mut self) -> EfficacyResult<Query> {
+        let token = match self.tokens.get(self.position) {
+            Some(t) => t.clone(),
+            None => return Err(query_error("Expected a term at the end of the filter")),
+        };
+        self.position += 1;
+
+        match token {
+            Token::LeftParen => {
+                let query = self.parse_or()?;
+                match self.peek() {
+                    Some(Token::RightParen) => {
+                        self.position += 1;
+                        Ok(query)
+                    }
+                    _ => Err(query_error("Missing ')'")),
+                }
+            }
+            Token::RightParen => Err(query_error("Unmatched ')'")),
+            Token::Word(word, true) => Ok(Query::Text(word)),
+            Token::Word(word, false) if word == "AND" || word == "OR" => {
+                Err(query_error(&format!("Expected a term before '{}'", word)))
+            }
+            Token::Word(word, false) => self.parse_keyed(&word),
+        }
+    }
+
+    fn parse_keyed(&self, word: &str) -> EfficacyResult<Query> {
+        let (key, value) = match word.find(':') {
+            Some(index) => (&word[..index], &word[index + 1..]),
+            None => return Ok(Query::Text(String::from(word))),
+        };
+
+        if value.is_empty() {
+            return Err(query_error(&format!("'{}' needs a value", key)));
+        }
+
+        match &key.to_lowercase()[..] {
+            "category" | "cat" => match &value.to_lowercase()[..] {
+                "none" => Ok(Query::Category(None)),
+                _ => Ok(Query::Category(Some(String::from(value)))),
+            },
+            "state" => match &value.to_lowercase()[..] {
+                "todo" => Ok(Query::State(TaskState::Todo)),
+                "done" => Ok(Query::State(TaskState::Done)),
+                _ => Err(query_error(&format!("'{}' is not 'todo' or 'done'", value))),
+            },
+            "tag" => Ok(Query::Tag(String::from(value.trim_start_matches('+')))),
+            "priority" => match value.parse() {
+                Ok(p) => Ok(Query::Priority(p)),
+                Err(e) => Err(query_error(&e)),
+            },
+            "due" => match &value.to_lowercase()[..] {
+                "none" => Ok(Query::HasDue(false)),
+                "any" => Ok(Query::HasDue(true)),
+                _ => Err(query_error(&format!("'{}' is not 'none' or 'any'", value))),
+            },
+            "due.before" => Ok(Query::DueBefore(self.date(value)?)),
+            "due.after" => Ok(Query::DueAfter(self.date(value)?)),
+            _ => Err(query_error(&format!(
+                "Unknown filter '{}'. Put text containing ':' in quotes to search for it",
+                key
+            ))),
+        }
+    }
+
+    fn date(&self, value: &str) -> EfficacyResult<DateTime<Utc>> {
+        match (self.parse_date)(value) {
+            Some(d) => Ok(d),
+            None => Err(query_error(&format!("'{}' is not a date", value))),
+        }
+    }
+}
+
+fn query_error(message: &str) -> EfficacyError {
+    EfficacyError::QueryError(String::from(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn parse(input: &str) -> EfficacyResult<Query> {
+        Query::parse(input, |d| match d {
+            "fri" => Some(Utc.ymd(2026, 10, 16).and_hms(8, 0, 0)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn parses_and_evaluates_filters() {
+        let mut task = Task::new(String::from("Write the quarterly report"));
+        task.category = Some(String::from("Work"));
+        task.tags.insert(String::from("urgent"));
+        task.due = Some(Utc.ymd(2026, 10, 15).and_hms(17, 0, 0));
+
+        let matching = [
+            "",
+            "category:work due.before:fri state:todo tag:urgent \"quarterly report\"",
+            "tag:later OR report",
+            "NOT (state:done OR due:none)",
+            "category:home OR (priority:none AND NOT due.after:fri)",
+        ];
+        for input in matching.iter() {
+            let query = match parse(input) {
+                Ok(q) => q,
+                Err(e) => panic!("Couldn't parse {:?}: {:?}", input, e),
+            };
+            assert!(query.matches(&task), "{:?} should match", input);
+        }
+
+        for input in [
+            "category:none",
+            "tag:urgent NOT report",
+            "\"report quarterly\"",
+        ]
+        .iter()
+        {
+            assert!(
+                !parse(input).unwrap().matches(&task),
+                "{:?} shouldn't match",
+                input
+            );
+        }
+
+        assert_eq!(
+            parse("a b OR c").unwrap(),
+            Query::Or(
+                Box::new(Query::And(
+                    Box::new(Query::Text(String::from("a"))),
+                    Box::new(Query::Text(String::from("b")))
+                )),
+                Box::new(Query::Text(String::from("c")))
+            )
+        );
+
+        for bad in [
+            "(tag:a",
+            "tag:a)",
+            "OR tag:a",
+            "state:maybe",
+            "due.before:someday",
+            "colour:red",
+            "\"open",
+        ]
+        .iter()
+        {
+            match parse(bad) {
+                Err(EfficacyError::QueryError(_)) => (),
+                other => panic!("Expected {:?} to fail, got {:?}", bad, other),
+            }
+        }
+    }
+
+    #[test]
+    fn selects_ids_ranges_and_filters() {
+        let select = |words: &[&str]| {
+            let words: Vec<String> = words.iter().map(|w| String::from(*w)).collect();
+            Selection::parse(&words, |_| None).unwrap()
+        };
+
+        let ranges = match select(&["3", "5", "7-9", "8"]) {
+            Selection::Ids(ranges) => ranges,
+            other => panic!("expected IDs, got {:?}", other),
+        };
+        assert_eq!(ranges, vec![(3, 3), (5, 5), (7, 9), (8, 8)]);
+        assert_eq!(expand_ids(&ranges, 10), Some(vec![3, 5, 7, 8, 9]));
+        assert_eq!(expand_ids(&ranges, 2), None);
+        assert_eq!(expand_ids(&[(4, 2)], 10), Some(vec![4, 3, 2]));
+        assert_eq!(expand_ids(&[(0, usize::MAX)], 10), None);
+        assert_eq!(
+            select(&["category:errands"]),
+            Selection::Matching(Query::Category(Some(String::from("errands"))))
+        );
+        assert_eq!(
+            select(&["\"3 reports\""]),
+            Selection::Matching(Query::Text(String::from("3 reports")))
+        );
+
+        // Mistyped ID lists are never searched for
+        for words in &[&["1,3"][..], &["4-"], &["1-2-3"], &["3", "report"]] {
+            let words: Vec<String> = words.iter().map(|w| String::from(*w)).collect();
+            assert!(Selection::parse(&words, |_| None).is_err());
+        }
+    }
+}
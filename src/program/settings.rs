@@ -1,15 +1,37 @@
+use chrono::NaiveTime;
 use config::{Config, ConfigError, File};
 use dirs;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use std::path::Path;
 
-use super::formatting;
+use super::{
+    formatting,
+    objects::{parse_time_of_day, GroupBy, OutputFormat, SortKey},
+    storage,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
     pub data_file_path: String,
     pub config_file_path: String,
     pub task_format: String,
+    /// Seconds to wait for another running command to release the data directory.
+    pub lock_timeout: u64,
+    /// Which storage backend keeps the tasks, either "json" or "sqlite". The first time
+    /// "sqlite" is used, the data kept as JSON is copied into the database.
+    pub storage: String,
+    /// Time of day that due dates given without a time are set to, written like `08:00` or
+    /// `5pm`.
+    #[serde(deserialize_with = "deserialize_time_of_day")]
+    pub default_due_time: NaiveTime,
+    /// What `list` sorts tasks by unless told otherwise.
+    pub sort: String,
+    /// What `list` groups tasks by unless told otherwise.
+    pub group_by: String,
+    /// How listings are printed: "text", "json" or "ndjson".
+    pub output: String,
+    /// Days deleted items are kept in the trash before being purged, or 0 to keep them forever.
+    pub trash_days: i64,
 }
 
 impl Settings {
@@ -34,7 +56,14 @@ impl Settings {
 
         // Defaults
         s.set_default("data_file_path", default_data_path.to_str())?;
-        s.set_default("task_format", "%b %d %i -> %D")?;
+        s.set_default("task_format", "%b %p %d %i -> %D")?;
+        s.set_default("lock_timeout", 5)?;
+        s.set_default("storage", "json")?;
+        s.set_default("default_due_time", "08:00")?;
+        s.set_default("sort", "priority")?;
+        s.set_default("group_by", "category")?;
+        s.set_default("output", "text")?;
+        s.set_default("trash_days", 30)?;
 
         if config_path.exists() {
             s.merge(File::from(config_path))?;
@@ -47,14 +76,49 @@ impl Settings {
         }
 
         // Ensures the format string is valid
-        if !formatting::valid_task_format(&s.get("task_format")?) {
-            s.set("task_format", "%b %d %i -> %D")?;
+        if !formatting::valid_task_format(&s.get::<String>("task_format")?) {
+            s.set("task_format", "%b %p %d %i -> %D")?;
+        }
+
+        // Ensures the storage backend exists
+        if !storage::STORAGE_BACKENDS.contains(&&s.get::<String>("storage")?[..]) {
+            s.set("storage", "json")?;
+        }
+
+        // Ensures the default due time is a time of day
+        if parse_time_of_day(&s.get::<String>("default_due_time")?).is_none() {
+            s.set("default_due_time", "08:00")?;
+        }
+
+        // Ensures the listing defaults are known
+        if s.get::<String>("sort")?.parse::<SortKey>().is_err() {
+            s.set("sort", "priority")?;
+        }
+        if s.get::<String>("group_by")?.parse::<GroupBy>().is_err() {
+            s.set("group_by", "category")?;
+        }
+        if s.get::<String>("output")?.parse::<OutputFormat>().is_err() {
+            s.set("output", "text")?;
+        }
+
+        // Ensures the trash is kept for a number of days
+        if s.get::<i64>("trash_days")? < 0 {
+            s.set("trash_days", 30)?;
         }
 
         s.try_into()
     }
 }
 
+fn deserialize_time_of_day<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let string = String::deserialize(deserializer)?;
+    parse_time_of_day(&string)
+        .ok_or_else(|| serde::de::Error::custom(format!("'{}' is not a time of day", string)))
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
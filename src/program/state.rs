@@ -1,221 +1,421 @@
-use std::collections::HashMap;
+use fs2::FileExt;
+use std::collections::{HashMap, HashSet};
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
 use std::path::PathBuf;
-
-use super::{errors::EfficacyError, objects, settings::Settings, EfficacyResult};
+use std::time::{Duration, Instant};
+
+use super::{
+    errors::EfficacyError,
+    journal::Journal,
+    objects,
+    settings::Settings,
+    storage::{self, Storage},
+    trash::{Trash, Trashed},
+    EfficacyResult,
+};
 
 #[derive(Debug)]
-pub struct State<'a> {
-    settings: &'a Settings,
-    context_file_path: PathBuf,
+pub struct State {
+    storage: Box<dyn Storage>,
     pub current_context: objects::Context,
-    pub task_file_paths: HashMap<String, PathBuf>,
     pub task_objects: Vec<objects::Task>,
     pub category_map: HashMap<String, Vec<usize>>,
+    /// Held for as long as this state has the data directory locked.
+    lock_file: Option<File>,
+    /// Set when loaded without the lock, in which case loading never writes anything.
+    read_only: bool,
 }
 
 // Core State functionality
-impl<'a> State<'a> {
-    /// Checks for existence of or creates directories and files used in maintaining state.
-    pub fn new(settings: &'a Settings) -> EfficacyResult<Self> {
-        let mut continue_creating = false;
-
-        let data_dir = PathBuf::from(&settings.data_file_path);
-        if !data_dir.exists() {
-            std::fs::create_dir(data_dir)?;
-            continue_creating = true;
-        }
-
-        let default_context = objects::Context {
-            context_name: String::from("default"),
-        };
-
-        let context_file_path =
-            PathBuf::from(&settings.data_file_path).join(PathBuf::from("context.json"));
-        if continue_creating || !context_file_path.exists() {
-            let context_serialized = serde_json::to_string(&default_context).unwrap();
-
-            OpenOptions::new()
-                .write(true)
-                .create_new(true)
-                .open(&context_file_path)?
-                .write(context_serialized.as_bytes())?;
+impl State {
+    /// Opens the storage backend picked in the settings and loads the current context from it.
+    /// Other commands may be running, so nothing is written while loading; use `new_locked` to
+    /// make changes.
+    pub fn new(settings: &Settings) -> EfficacyResult<Self> {
+        State::open(settings, None)
+    }
 
-            continue_creating = true;
-        }
+    /// Like `new`, but first takes an exclusive lock on the data directory that is held until the
+    /// state is dropped. Waits for up to `lock_timeout` seconds for other commands to finish.
+    pub fn new_locked(settings: &Settings) -> EfficacyResult<Self> {
+        let lock_file = lock_data_dir(settings)?;
 
-        let default_task_file_path =
-            PathBuf::from(&settings.data_file_path).join(PathBuf::from("default.json"));
-        if continue_creating || !default_task_file_path.exists() {
-            File::create(&default_task_file_path)?;
-            continue_creating = true;
-        }
+        State::open(settings, Some(lock_file))
+    }
 
-        let mut task_file_paths = HashMap::new();
+    fn open(settings: &Settings, lock_file: Option<File>) -> EfficacyResult<Self> {
+        let read_only = lock_file.is_none();
+        let loaded = storage::open(settings, read_only)
+            .and_then(|storage| State::load_from(storage, read_only));
 
-        for entry in std::fs::read_dir(&settings.data_file_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if !path.as_os_str().eq(context_file_path.as_os_str())
-                && !path.as_os_str().eq(default_task_file_path.as_os_str())
-            {
-                let path_key = String::from(path.file_stem().unwrap().to_str().unwrap());
-                task_file_paths.insert(path_key, path);
+        match loaded {
+            Ok(mut state) => {
+                state.lock_file = lock_file;
+                Ok(state)
+            }
+            Err(e) => {
+                println!("Issue when loading state for the first time...");
+                Err(e)
             }
         }
+    }
 
-        task_file_paths.insert(String::from("default"), default_task_file_path);
+    /// Loads the current context from any storage backend.
+    pub fn with_storage(storage: Box<dyn Storage>) -> EfficacyResult<Self> {
+        State::load_from(storage, false)
+    }
 
+    fn load_from(storage: Box<dyn Storage>, read_only: bool) -> EfficacyResult<Self> {
         let mut new_state = State {
-            settings,
-            context_file_path,
-            current_context: default_context,
-            task_file_paths,
+            storage,
+            current_context: objects::Context {
+                context_name: String::from("default"),
+                next_ids: HashMap::new(),
+            },
             task_objects: Vec::new(),
             category_map: HashMap::new(),
+            lock_file: None,
+            read_only,
         };
 
-        if !continue_creating {
-            match new_state.load() {
-                Ok(_) => (),
-                Err(e) => {
-                    println!("Issue when loading state for the first time...");
-                    return Err(e);
-                }
-            };
-        }
+        new_state.load()?;
 
         Ok(new_state)
     }
 
-    pub fn save(&mut self) -> EfficacyResult<()> {
-        let tasks_serialized = serde_json::to_string(&self.task_objects).unwrap();
+    /// Replaces data that can't be read with the versions from before the last save. Returns
+    /// the files that were restored.
+    pub fn recover(settings: &Settings) -> EfficacyResult<Vec<PathBuf>> {
+        let _lock_file = lock_data_dir(settings)?;
 
-        let fp_result = &self.task_file_paths.get(&self.current_context.context_name);
-
-        let file_path = match fp_result {
-            Some(pb) => pb,
-            None => return Err(EfficacyError::MalformedContextError),
-        };
+        storage::recover(settings)
+    }
 
-        OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(file_path)?
-            .write(tasks_serialized.as_bytes())?;
+    pub fn save(&mut self) -> EfficacyResult<()> {
+        self.storage
+            .save_tasks(&self.current_context.context_name, &self.task_objects)?;
 
         self.save_context()
     }
 
     pub fn load(&mut self) -> EfficacyResult<()> {
-        let mut tasks_string = String::new();
-
         self.load_context()?;
 
-        let fp_result = &self.task_file_paths.get(&self.current_context.context_name);
-        let file_path = match fp_result {
-            Some(pb) => pb,
-            None => return Err(EfficacyError::MalformedContextError),
-        };
+        self.task_objects = self
+            .storage
+            .load_tasks(&self.current_context.context_name)?;
 
-        OpenOptions::new()
-            .read(true)
-            .open(file_path)?
-            .read_to_string(&mut tasks_string)?;
+        let ids_assigned = self.assign_missing_ids();
+        // Files edited by hand can hold trees that never end
+        let cycles_broken = break_parent_cycles(&mut self.task_objects);
+        self.rebuild_category_map();
 
-        let tasks: Vec<objects::Task> = match serde_json::from_str(&tasks_string[..]) {
-            Ok(o) => o,
-            Err(_) => Vec::new(),
-        };
+        // Read-only states hand out the same IDs again on every load until a locked one saves
+        if (ids_assigned || cycles_broken) && !self.read_only {
+            self.save()?;
+        }
 
-        for (id, task) in tasks.iter().enumerate() {
-            self.add_to_category_map(task, id);
+        Ok(())
+    }
+}
+
+/// Like `State::next_task_id`, for the tasks of any context. `next_ids` holds the ID counter of
+/// every context.
+fn next_id_in(
+    next_ids: &mut HashMap<String, usize>,
+    context_name: &str,
+    tasks: &[objects::Task],
+) -> usize {
+    let floor = tasks
+        .iter()
+        .filter(|t| t.id != objects::UNASSIGNED_ID)
+        .map(|t| t.id + 1)
+        .max()
+        .unwrap_or(0);
+
+    let counter = next_ids.entry(String::from(context_name)).or_insert(0);
+    let id = std::cmp::max(*counter, floor);
+    *counter = id + 1;
+
+    id
+}
+
+/// Like `State::assign_missing_ids`, for the tasks of any context.
+pub fn assign_missing_ids_in(
+    next_ids: &mut HashMap<String, usize>,
+    context_name: &str,
+    tasks: &mut [objects::Task],
+) -> bool {
+    let mut migrated = false;
+
+    for index in 0..tasks.len() {
+        if tasks[index].id == objects::UNASSIGNED_ID {
+            tasks[index].id = next_id_in(next_ids, context_name, tasks);
+            migrated = true;
         }
+    }
 
-        self.task_objects = tasks;
+    migrated
+}
 
-        Ok(())
+fn lock_data_dir(settings: &Settings) -> EfficacyResult<File> {
+    let data_dir = PathBuf::from(&settings.data_file_path);
+    std::fs::create_dir_all(&data_dir)?;
+
+    let lock_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(data_dir.join("efficacy.lock"))?;
+
+    let deadline = Instant::now() + Duration::from_secs(settings.lock_timeout);
+    while lock_file.try_lock_exclusive().is_err() {
+        if Instant::now() >= deadline {
+            return Err(EfficacyError::LockTimeoutError);
+        }
+        std::thread::sleep(Duration::from_millis(100));
     }
+
+    Ok(lock_file)
 }
 
-// Category map operations
-impl<'a> State<'a> {
-    pub fn add_to_category_map(&mut self, task: &objects::Task, id: usize) {
-        match &task.category {
-            Some(c) => match self.category_map.get_mut(c) {
-                Some(v) => v.push(id),
-                None => {
-                    self.category_map.insert(c.to_string(), vec![id]);
+// Task ID operations
+impl State {
+    /// Hands out the next unused task ID for the current context. IDs are never reused, even
+    /// after the task holding one is deleted.
+    pub fn next_task_id(&mut self) -> usize {
+        let context_name = self.current_context.context_name.clone();
+        next_id_in(
+            &mut self.current_context.next_ids,
+            &context_name,
+            &self.task_objects,
+        )
+    }
+
+    /// Gives an ID to every task loaded from a file that predates stored IDs. Tasks are numbered
+    /// in file order, so they keep the IDs they were previously displayed with.
+    fn assign_missing_ids(&mut self) -> bool {
+        let context_name = self.current_context.context_name.clone();
+        assign_missing_ids_in(
+            &mut self.current_context.next_ids,
+            &context_name,
+            &mut self.task_objects,
+        )
+    }
+
+    pub fn task_index(&self, id: usize) -> Option<usize> {
+        self.task_objects.iter().position(|t| t.id == id)
+    }
+
+    pub fn get_task(&self, id: usize) -> Option<&objects::Task> {
+        self.task_objects.iter().find(|t| t.id == id)
+    }
+
+    pub fn get_task_mut(&mut self, id: usize) -> Option<&mut objects::Task> {
+        self.task_objects.iter_mut().find(|t| t.id == id)
+    }
+}
+
+// Subtask operations
+impl State {
+    pub fn subtask_ids(&self, id: usize) -> Vec<usize> {
+        self.task_objects
+            .iter()
+            .filter(|t| t.parent == Some(id))
+            .map(|t| t.id)
+            .collect()
+    }
+
+    /// Every task below the given one in its tree, nearest first. Each task is listed once,
+    /// even if its parents lead back around to it.
+    pub fn descendant_ids(&self, id: usize) -> Vec<usize> {
+        let mut visited = HashSet::new();
+        visited.insert(id);
+
+        let mut descendants: Vec<usize> = Vec::new();
+        let mut parent = Some(id);
+        let mut next = 0;
+
+        while let Some(current) = parent {
+            for child in self.subtask_ids(current) {
+                if visited.insert(child) {
+                    descendants.push(child);
                 }
-            },
-            None => match self.category_map.get_mut("No category") {
-                Some(v) => v.push(id),
-                None => {
-                    self.category_map
-                        .insert(String::from("No category"), vec![id]);
+            }
+            parent = descendants.get(next).cloned();
+            next += 1;
+        }
+
+        descendants
+    }
+
+    /// Drops parents and dependencies that point at tasks which no longer exist. Subtasks whose
+    /// parent is gone become top level tasks.
+    pub fn prune_references(&mut self) {
+        prune_references_in(&mut self.task_objects);
+    }
+}
+
+/// Makes tasks whose chain of parents leads back to themselves top level tasks, so every tree
+/// has a root. Returns whether any parent was dropped.
+pub fn break_parent_cycles(tasks: &mut [objects::Task]) -> bool {
+    let mut parents: HashMap<usize, usize> = tasks
+        .iter()
+        .filter_map(|t| t.parent.map(|p| (t.id, p)))
+        .collect();
+
+    let mut broken = false;
+    for task in tasks.iter() {
+        let mut visited = HashSet::new();
+        let mut current = task.id;
+        while let Some(&parent) = parents.get(&current) {
+            if !visited.insert(current) {
+                parents.remove(&current);
+                broken = true;
+                break;
+            }
+            current = parent;
+        }
+    }
+
+    for task in tasks.iter_mut() {
+        task.parent = parents.get(&task.id).cloned();
+    }
+
+    broken
+}
+
+/// Like `State::prune_references`, for the tasks of any context.
+pub fn prune_references_in(tasks: &mut [objects::Task]) {
+    let ids: HashSet<usize> = tasks.iter().map(|t| t.id).collect();
+
+    for task in tasks.iter_mut() {
+        if let Some(parent) = task.parent {
+            if !ids.contains(&parent) {
+                task.parent = None;
+            }
+        }
+        task.depends_on.retain(|id| ids.contains(id));
+    }
+}
+
+// Dependency operations
+impl State {
+    /// Dependencies of the given task that are still to do.
+    pub fn open_blocker_ids(&self, id: usize) -> Vec<usize> {
+        let task = match self.get_task(id) {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+
+        task.depends_on
+            .iter()
+            .filter(|dependency| match self.get_task(**dependency) {
+                Some(t) => t.state == objects::TaskState::Todo,
+                None => false,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `id` depends on `other`, directly or through other dependencies.
+    pub fn depends_on(&self, id: usize, other: usize) -> bool {
+        let mut to_visit = vec![id];
+        let mut visited = HashSet::new();
+
+        while let Some(current) = to_visit.pop() {
+            if !visited.insert(current) {
+                continue;
+            }
+            if let Some(task) = self.get_task(current) {
+                if task.depends_on.contains(&other) {
+                    return true;
                 }
-            },
+                to_visit.extend(task.depends_on.iter().cloned());
+            }
         }
+
+        false
+    }
+}
+
+// Category map operations
+impl State {
+    pub fn add_to_category_map(&mut self, task: &objects::Task) {
+        self.category_map
+            .entry(category_key(task))
+            .or_default()
+            .push(task.id);
     }
 
     pub fn rebuild_category_map(&mut self) {
         self.category_map.clear();
 
-        for (id, task) in self.task_objects.iter().enumerate() {
-            match &task.category {
-                Some(c) => match self.category_map.get_mut(c) {
-                    Some(v) => v.push(id),
-                    None => {
-                        self.category_map.insert(c.to_string(), vec![id]);
-                    }
-                },
-                None => match self.category_map.get_mut("No category") {
-                    Some(v) => v.push(id),
-                    None => {
-                        self.category_map
-                            .insert(String::from("No category"), vec![id]);
-                    }
-                },
-            }
+        for task in &self.task_objects {
+            self.category_map
+                .entry(category_key(task))
+                .or_default()
+                .push(task.id);
         }
     }
 }
 
-// Context operations
-impl<'a> State<'a> {
-    pub fn save_context(&self) -> EfficacyResult<()> {
-        let context_serialized = serde_json::to_string(&self.current_context).unwrap();
+pub fn category_key(task: &objects::Task) -> String {
+    match &task.category {
+        Some(c) => c.to_string(),
+        None => String::from("No category"),
+    }
+}
 
-        OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&self.context_file_path)?
-            .write(context_serialized.as_bytes())?;
+// Transactions
+impl State {
+    /// See `Storage::begin`.
+    pub fn begin(&mut self) -> EfficacyResult<()> {
+        self.storage.begin()
+    }
 
-        Ok(())
+    pub fn commit(&mut self) -> EfficacyResult<()> {
+        self.storage.commit()
     }
 
-    pub fn load_context(&mut self) -> EfficacyResult<()> {
-        let mut ctx_string = String::new();
+    pub fn rollback(&mut self) -> EfficacyResult<()> {
+        self.storage.rollback()
+    }
+}
 
-        OpenOptions::new()
-            .read(true)
-            .open(&self.context_file_path)?
-            .read_to_string(&mut ctx_string)?;
+// Journal operations
+impl State {
+    pub fn load_journal(&mut self) -> EfficacyResult<Journal> {
+        self.storage.load_journal()
+    }
 
-        self.current_context = match serde_json::from_str(&ctx_string[..]) {
-            Ok(o) => o,
-            Err(_) => objects::Context {
-                context_name: String::from("default"),
-            },
-        };
+    pub fn save_journal(&mut self, journal: &Journal) -> EfficacyResult<()> {
+        self.storage.save_journal(journal)
+    }
+
+    pub fn load_trash(&mut self) -> EfficacyResult<Trash> {
+        self.storage.load_trash()
+    }
+
+    pub fn save_trash(&mut self, trash: &Trash) -> EfficacyResult<()> {
+        self.storage.save_trash(trash)
+    }
+}
+
+// Context operations
+impl State {
+    pub fn save_context(&mut self) -> EfficacyResult<()> {
+        self.storage.save_context(&self.current_context)
+    }
+
+    pub fn load_context(&mut self) -> EfficacyResult<()> {
+        self.current_context = self.storage.load_context()?;
 
         Ok(())
     }
 
-    pub fn new_context(&mut self, context_name: &String) -> EfficacyResult<()> {
-        if context_name.eq(&String::from("default")) {
+    pub fn new_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        if context_name.eq("default") {
             println!(
                 "Cannot make a new context with the name 'default', that context is reserved."
             );
@@ -223,35 +423,29 @@ impl<'a> State<'a> {
         }
 
         let trimmed_context_name = String::from(context_name.trim());
-        if trimmed_context_name.contains(" ") {
+        if trimmed_context_name.is_empty() || trimmed_context_name.contains(' ') {
             return Err(EfficacyError::BadContextNameError);
         }
 
-        let new_context_task_file_path = PathBuf::from(&self.settings.data_file_path).join(
-            PathBuf::from(format!("{}{}", &trimmed_context_name, ".json")),
-        );
-
-        std::fs::File::create(&new_context_task_file_path)?;
+        if self.context_exists(&trimmed_context_name)? {
+            println!("Context '{}' already exists", trimmed_context_name);
+            return Err(EfficacyError::BadContextNameError);
+        }
 
-        self.task_file_paths
-            .insert(trimmed_context_name.clone(), new_context_task_file_path);
+        self.storage.create_context(&trimmed_context_name)?;
 
         self.change_context(&trimmed_context_name)
     }
 
-    pub fn change_context(&mut self, context_name: &String) -> EfficacyResult<()> {
-        if !self.context_exists(context_name) {
+    pub fn change_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        if !self.context_exists(context_name)? {
             println!("Context '{}' does not exist", context_name);
             return Err(EfficacyError::BadContextNameError);
         }
 
         self.save()?;
 
-        if !self.task_file_paths.contains_key(context_name) {
-            return Err(EfficacyError::BadContextNameError);
-        }
-
-        self.current_context.context_name = context_name.clone();
+        self.current_context.context_name = context_name.to_string();
         self.save_context()?;
         self.load()?;
         self.rebuild_category_map();
@@ -259,7 +453,9 @@ impl<'a> State<'a> {
         Ok(())
     }
 
-    pub fn delete_context(&mut self, context_name: &String) -> EfficacyResult<()> {
+    /// Deletes a context, keeping its tasks, archive and ID counter in `trash`. The trash is
+    /// saved before anything is removed.
+    pub fn delete_context(&mut self, context_name: &str, trash: &mut Trash) -> EfficacyResult<()> {
         if context_name.eq("default") {
             println!("Cannot delete the default context.");
             return Ok(());
@@ -272,63 +468,138 @@ impl<'a> State<'a> {
             return Ok(());
         }
 
-        let file_to_delete = match self.task_file_paths.get(context_name) {
-            Some(p) => p,
-            None => {
-                println!("Context given does not exist.");
-                return Ok(());
-            }
+        if !self.context_exists(context_name)? {
+            println!("Context given does not exist.");
+            return Ok(());
+        }
+
+        let item = Trashed::Context {
+            tasks: self.storage.load_tasks(context_name)?,
+            archive: self.storage.load_archive(context_name)?,
+            next_id: self.current_context.next_ids.get(context_name).cloned(),
         };
+        trash.add(context_name, item);
+        self.save_trash(trash)?;
 
-        std::fs::remove_file(file_to_delete)?;
+        self.storage.delete_context(context_name)?;
+        self.current_context.next_ids.remove(context_name);
 
-        Ok(())
+        let mut journal = self.load_journal()?;
+        journal.forget_context(context_name);
+        self.save_journal(&journal)?;
+
+        self.save_context()
     }
 
-    pub fn context_exists(&self, context_name: &String) -> bool {
-        match self.task_file_paths.get(context_name) {
-            Some(_) => true,
-            None => false,
+    /// Tasks of any context, without switching to it.
+    pub fn tasks_in_context(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        if context_name == self.current_context.context_name {
+            return Ok(self.task_objects.clone());
         }
+
+        // Other contexts get the same migrations as the current one does in `load`
+        let mut tasks = self.storage.load_tasks(context_name)?;
+        let ids_assigned =
+            assign_missing_ids_in(&mut self.current_context.next_ids, context_name, &mut tasks);
+        let cycles_broken = break_parent_cycles(&mut tasks);
+
+        if (ids_assigned || cycles_broken) && !self.read_only {
+            self.storage.save_tasks(context_name, &tasks)?;
+            self.save_context()?;
+        }
+
+        Ok(tasks)
+    }
+
+    /// Replaces the tasks of any context, without switching to it.
+    pub fn save_tasks_in_context(
+        &mut self,
+        context_name: &str,
+        tasks: Vec<objects::Task>,
+    ) -> EfficacyResult<()> {
+        if context_name != self.current_context.context_name {
+            return self.storage.save_tasks(context_name, &tasks);
+        }
+
+        self.task_objects = tasks;
+        self.rebuild_category_map();
+        self.save()
+    }
+
+    /// Brings back a context deleted with `delete_context`, with the tasks, archive and ID
+    /// counter it had.
+    pub fn restore_context(
+        &mut self,
+        context_name: &str,
+        tasks: &[objects::Task],
+        archive: &[objects::Task],
+        next_id: Option<usize>,
+    ) -> EfficacyResult<()> {
+        self.storage.create_context(context_name)?;
+        self.storage.save_archive(context_name, archive)?;
+        self.storage.save_tasks(context_name, tasks)?;
+
+        if let Some(next_id) = next_id {
+            self.current_context
+                .next_ids
+                .insert(String::from(context_name), next_id);
+        }
+
+        self.save_context()
+    }
+
+    /// The archive of any context.
+    pub fn archive_in_context(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        self.storage.load_archive(context_name)
+    }
+
+    pub fn load_archive(&mut self) -> EfficacyResult<Vec<objects::Task>> {
+        self.storage
+            .load_archive(&self.current_context.context_name)
+    }
+
+    pub fn save_archive(&mut self, archive: &[objects::Task]) -> EfficacyResult<()> {
+        self.storage
+            .save_archive(&self.current_context.context_name, archive)
+    }
+
+    pub fn context_names(&self) -> EfficacyResult<Vec<String>> {
+        self.storage.context_names()
+    }
+
+    pub fn context_exists(&self, context_name: &str) -> EfficacyResult<bool> {
+        Ok(self
+            .context_names()?
+            .iter()
+            .any(|name| name == context_name))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::objects::{Task, TaskState};
+    use super::storage::MemoryStorage;
     use super::State;
 
     fn generate_test_tasks() -> Vec<Task> {
-        let task_1 = Task {
-            description: String::from("Add classes to calendar"),
-            state: TaskState::Done,
-            category: Option::Some(String::from("School")),
-            information: Some(String::new()),
-            due: None,
-        };
-        let task_2 = Task {
-            description: String::from("Study for exam"),
-            state: TaskState::Todo,
-            category: Option::Some(String::from("School")),
-            information: Some(String::new()),
-            due: None,
-        };
-        let task_3 = Task {
-            description: String::from("Get haircut"),
-            state: TaskState::Todo,
-            category: Option::Some(String::from("Personal")),
-            information: Some(String::new()),
-            due: None,
-        };
-        let task_4 = Task {
-            description: String::from("Workout"),
-            state: TaskState::Todo,
-            category: Option::None,
-            information: Some(String::new()),
-            due: None,
-        };
-
-        vec![task_1, task_2, task_3, task_4]
+        let tasks = [
+            ("Add classes to calendar", Some("School"), TaskState::Done),
+            ("Study for exam", Some("School"), TaskState::Todo),
+            ("Get haircut", Some("Personal"), TaskState::Todo),
+            ("Workout", None, TaskState::Todo),
+        ];
+
+        tasks
+            .iter()
+            .enumerate()
+            .map(|(id, (description, category, state))| {
+                let mut task = Task::new(String::from(*description));
+                task.id = id;
+                task.category = category.map(String::from);
+                task.state = state.clone();
+                task
+            })
+            .collect()
     }
 
     #[test]
@@ -344,4 +615,192 @@ mod test {
         state.save().unwrap();
         state.load().unwrap();
     }
+
+    #[test]
+    fn legacy_ids_are_migrated() {
+        use super::objects::UNASSIGNED_ID;
+
+        let mut state = State::with_storage(Box::new(MemoryStorage::default())).unwrap();
+
+        let mut tasks = generate_test_tasks();
+        for task in tasks.iter_mut() {
+            task.id = UNASSIGNED_ID;
+        }
+        state.task_objects = tasks;
+        state.current_context.next_ids.clear();
+
+        assert!(state.assign_missing_ids());
+        let ids: Vec<usize> = state.task_objects.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+
+        state.task_objects.remove(3);
+        assert_eq!(state.next_task_id(), 4);
+
+        // Contexts that are only read from get IDs too, and keep them
+        let mut work_tasks = generate_test_tasks();
+        for task in work_tasks.iter_mut() {
+            task.id = UNASSIGNED_ID;
+        }
+        state.storage.create_context("work").unwrap();
+        state.storage.save_tasks("work", &work_tasks).unwrap();
+        let ids: Vec<usize> = state
+            .tasks_in_context("work")
+            .unwrap()
+            .iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec![0, 1, 2, 3]);
+        assert_eq!(state.storage.load_tasks("work").unwrap()[3].id, 3);
+    }
+
+    #[test]
+    fn dependency_chains() {
+
+        let mut state = State::with_storage(Box::new(MemoryStorage::default())).unwrap();
+
+        let mut tasks = generate_test_tasks();
+        tasks[1].depends_on.insert(0);
+        tasks[2].depends_on.insert(1);
+        state.task_objects = tasks;
+
+        assert!(state.depends_on(2, 0));
+        assert!(!state.depends_on(0, 2));
+        // Task 0 is done, so only task 1 still blocks task 2
+        assert!(state.open_blocker_ids(1).is_empty());
+        assert_eq!(state.open_blocker_ids(2), vec![1]);
+    }
+
+    #[test]
+    fn parent_cycles_are_broken() {
+        let mut state = State::with_storage(Box::new(MemoryStorage::default())).unwrap();
+
+        let mut tasks = generate_test_tasks();
+        tasks[0].parent = Some(0);
+        tasks[1].parent = Some(2);
+        tasks[2].parent = Some(1);
+        tasks[3].parent = Some(1);
+        state.task_objects = tasks;
+
+        // Walking the trees still ends
+        assert!(state.descendant_ids(0).is_empty());
+        assert_eq!(state.descendant_ids(1), vec![2, 3]);
+
+        state.save().unwrap();
+        state.load().unwrap();
+        let parents: Vec<Option<usize>> = state.task_objects.iter().map(|t| t.parent).collect();
+        assert_eq!(parents, vec![None, None, Some(1), Some(1)]);
+    }
+
+    #[test]
+    fn refuses_to_overwrite_outside_changes() {
+        use super::super::errors::EfficacyError;
+        use super::super::test_support::test_settings;
+
+        let data_dir = std::env::temp_dir().join("efficacy_outside_changes_test");
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let config = test_settings(&data_dir);
+
+        let mut state = State::new_locked(&config).unwrap();
+        state.task_objects = generate_test_tasks();
+        state.save().unwrap();
+
+        std::fs::write(data_dir.join("default.json"), "[]").unwrap();
+        match state.save() {
+            Err(EfficacyError::FileChangedError(_)) => (),
+            other => panic!("Expected a FileChangedError, got {:?}", other),
+        }
+
+        // The lock is held until the state is dropped
+        assert!(State::new_locked(&config).is_err());
+        drop(state);
+        assert!(State::new_locked(&config).is_ok());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn unlocked_loads_write_nothing() {
+        use super::super::test_support::test_settings;
+
+        let data_dir = std::env::temp_dir().join("efficacy_unlocked_load_test");
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let config = test_settings(&data_dir);
+
+        // A version 1 file, whose tasks have no IDs yet
+        let version_1 = r#"[{"description":"Workout","state":"Todo","category":null,"information":null,"due":null}]"#;
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let task_file_path = data_dir.join("default.json");
+        std::fs::write(&task_file_path, version_1).unwrap();
+        let backup_path = data_dir.join("default.json.v1.bak");
+
+        let state = State::new(&config).unwrap();
+        assert_eq!(state.task_objects[0].id, 0);
+        assert_eq!(std::fs::read_to_string(&task_file_path).unwrap(), version_1);
+        assert!(!backup_path.exists());
+        drop(state);
+
+        let state = State::new_locked(&config).unwrap();
+        assert_eq!(state.task_objects[0].id, 0);
+        assert_ne!(std::fs::read_to_string(&task_file_path).unwrap(), version_1);
+        assert!(backup_path.exists());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files_are_kept_and_recoverable() {
+        use super::super::errors::EfficacyError;
+        use super::super::test_support::test_settings;
+
+        let data_dir = std::env::temp_dir().join("efficacy_corrupt_file_test");
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let config = test_settings(&data_dir);
+
+        {
+            let mut state = State::new(&config).unwrap();
+            state.task_objects = generate_test_tasks();
+            state.save().unwrap();
+            state.task_objects.pop();
+            state.save().unwrap();
+        }
+
+        let task_file_path = data_dir.join("default.json");
+        std::fs::write(&task_file_path, "{\n  \"version\": 2,\n  oops").unwrap();
+
+        match State::new(&config) {
+            Err(EfficacyError::CorruptFileError {
+                line,
+                quarantine_path: Some(quarantine_path),
+                ..
+            }) => {
+                assert_eq!(line, 3);
+                assert!(quarantine_path.exists());
+            }
+            other => panic!("Expected a CorruptFileError, got {:?}", other),
+        }
+        // Nothing was overwritten
+        assert!(std::fs::read_to_string(&task_file_path)
+            .unwrap()
+            .ends_with("oops"));
+
+        assert_eq!(State::recover(&config).unwrap(), vec![task_file_path]);
+        let mut state = State::new(&config).unwrap();
+        assert_eq!(state.task_objects.len(), 4);
+
+        let journal = state.load_journal().unwrap();
+        state.save_journal(&journal).unwrap();
+        state.save_journal(&journal).unwrap();
+        let journal_file_path = data_dir.join("journal.json");
+        std::fs::write(&journal_file_path, "{\"entries\": oops").unwrap();
+        match state.load_journal() {
+            Err(EfficacyError::CorruptFileError { path, .. }) => {
+                assert_eq!(path, journal_file_path)
+            }
+            other => panic!("Expected a CorruptFileError, got {:?}", other),
+        }
+        assert_eq!(State::recover(&config).unwrap(), vec![journal_file_path]);
+        assert!(state.load_journal().is_ok());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
 }
@@ -0,0 +1,436 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::super::{
+    errors::EfficacyError, journal::Journal, migrations, objects, trash::Trash, EfficacyResult,
+};
+use super::Storage;
+
+/// Files in the data directory that don't hold the tasks of a context.
+const RESERVED_FILE_STEMS: [&str; 2] = ["context", "journal"];
+
+/// The original layout: one JSON file of tasks per context, plus `context.json` and
+/// `journal.json`, all in the data directory. Archives are kept in files of the same name in
+/// `archive/`, and the trash in `trash/trash.json`.
+#[derive(Debug)]
+pub struct JsonStorage {
+    data_dir: PathBuf,
+    /// Fingerprints of task files as they were last loaded or saved, by context name.
+    fingerprints: HashMap<String, u64>,
+    /// Whether files from older versions are left as they are when loaded.
+    read_only: bool,
+}
+
+impl JsonStorage {
+    /// Checks for existence of or creates the data directory and the files always found in it.
+    pub fn open(data_dir: &Path, read_only: bool) -> EfficacyResult<Self> {
+        std::fs::create_dir_all(data_dir)?;
+
+        let context_file_path = data_dir.join("context.json");
+        if !context_file_path.exists() {
+            let default_context = objects::Context {
+                context_name: String::from("default"),
+                next_ids: HashMap::new(),
+            };
+            let context_serialized = serde_json::to_string(&default_context).unwrap();
+
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&context_file_path)?
+                .write_all(context_serialized.as_bytes())?;
+        }
+
+        let default_task_file_path = data_dir.join("default.json");
+        if !default_task_file_path.exists() {
+            File::create(&default_task_file_path)?;
+        }
+
+        Ok(JsonStorage {
+            data_dir: data_dir.to_path_buf(),
+            fingerprints: HashMap::new(),
+            read_only,
+        })
+    }
+
+    /// Whether the data directory holds data from this backend.
+    pub fn exists(data_dir: &Path) -> bool {
+        data_dir.join("context.json").exists()
+    }
+
+    /// Replaces data files that can't be read with their `.bak` copies, which are the versions
+    /// from before the last save. Broken files are quarantined first. Returns the files that
+    /// were restored.
+    pub fn recover(data_dir: &Path) -> EfficacyResult<Vec<PathBuf>> {
+        let mut restored = Vec::new();
+
+        let context_file_path = data_dir.join("context.json");
+        if context_file_path.exists()
+            && restore_if_unreadable(&context_file_path, |contents| {
+                serde_json::from_str::<objects::Context>(contents)?;
+                Ok(())
+            })?
+        {
+            restored.push(context_file_path);
+        }
+
+        let journal_file_path = data_dir.join("journal.json");
+        if journal_file_path.exists()
+            && restore_if_unreadable(&journal_file_path, |contents| {
+                serde_json::from_str::<Journal>(contents)?;
+                Ok(())
+            })?
+        {
+            restored.push(journal_file_path);
+        }
+
+        let trash_file_path = data_dir.join("trash").join("trash.json");
+        if trash_file_path.exists()
+            && restore_if_unreadable(&trash_file_path, |contents| {
+                serde_json::from_str::<Trash>(contents)?;
+                Ok(())
+            })?
+        {
+            restored.push(trash_file_path);
+        }
+
+        let archive_dir = data_dir.join("archive");
+        let mut paths = task_file_paths(data_dir)?;
+        if archive_dir.exists() {
+            paths.extend(task_file_paths(&archive_dir)?);
+        }
+
+        for path in paths {
+            if restore_if_unreadable(&path, |contents| {
+                migrations::deserialize_tasks(contents)?;
+                Ok(())
+            })? {
+                restored.push(path);
+            }
+        }
+
+        Ok(restored)
+    }
+
+    fn context_file_path(&self) -> PathBuf {
+        self.data_dir.join("context.json")
+    }
+
+    fn journal_file_path(&self) -> PathBuf {
+        self.data_dir.join("journal.json")
+    }
+
+    fn task_file_path(&self, context_name: &str) -> PathBuf {
+        self.data_dir.join(format!("{}.json", context_name))
+    }
+
+    fn trash_file_path(&self) -> PathBuf {
+        self.data_dir.join("trash").join("trash.json")
+    }
+
+    fn archive_file_path(&self, context_name: &str) -> PathBuf {
+        self.data_dir
+            .join("archive")
+            .join(format!("{}.json", context_name))
+    }
+}
+
+impl Storage for JsonStorage {
+    fn context_names(&self) -> EfficacyResult<Vec<String>> {
+        Ok(task_file_paths(&self.data_dir)?
+            .iter()
+            .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(String::from))
+            .collect())
+    }
+
+    fn load_context(&mut self) -> EfficacyResult<objects::Context> {
+        let context_file_path = self.context_file_path();
+        let ctx_string = std::fs::read_to_string(&context_file_path)?;
+
+        match serde_json::from_str(&ctx_string[..]) {
+            Ok(o) => Ok(o),
+            Err(e) => Err(corrupt_file_error(&context_file_path, &ctx_string, e)),
+        }
+    }
+
+    fn save_context(&mut self, context: &objects::Context) -> EfficacyResult<()> {
+        let context_serialized = serde_json::to_string(context).unwrap();
+
+        write_atomically(&self.context_file_path(), context_serialized.as_bytes())
+    }
+
+    fn load_tasks(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        let file_path = self.task_file_path(context_name);
+        if !file_path.exists() {
+            return Err(EfficacyError::MalformedContextError);
+        }
+
+        let tasks_string = std::fs::read_to_string(&file_path)?;
+
+        let (tasks, version) = match migrations::deserialize_tasks(&tasks_string[..]) {
+            Ok(loaded) => loaded,
+            Err(EfficacyError::SerdeJsonError(e)) => {
+                return Err(corrupt_file_error(&file_path, &tasks_string, e))
+            }
+            Err(e) => return Err(e),
+        };
+        self.fingerprints.insert(
+            String::from(context_name),
+            fingerprint(tasks_string.as_bytes()),
+        );
+
+        // Keep a copy of files from older versions before they're rewritten
+        if version < migrations::CURRENT_VERSION && !self.read_only {
+            let backup_path = with_added_extension(&file_path, &format!("v{}.bak", version));
+            std::fs::copy(&file_path, backup_path)?;
+            self.save_tasks(context_name, &tasks)?;
+        }
+
+        Ok(tasks)
+    }
+
+    fn save_tasks(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        let file_path = self.task_file_path(context_name);
+        if !file_path.exists() {
+            return Err(EfficacyError::MalformedContextError);
+        }
+
+        // Refuse to clobber changes made by someone else since we loaded
+        if let Some(loaded) = self.fingerprints.get(context_name) {
+            if fingerprint(&std::fs::read(&file_path)?) != *loaded {
+                return Err(EfficacyError::FileChangedError(file_path));
+            }
+        }
+
+        let tasks_serialized = migrations::serialize_tasks(tasks).unwrap();
+        write_atomically(&file_path, tasks_serialized.as_bytes())?;
+        self.fingerprints.insert(
+            String::from(context_name),
+            fingerprint(tasks_serialized.as_bytes()),
+        );
+
+        Ok(())
+    }
+
+    fn load_archive(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        let file_path = self.archive_file_path(context_name);
+        if !file_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let tasks_string = std::fs::read_to_string(&file_path)?;
+
+        match migrations::deserialize_tasks(&tasks_string[..]) {
+            Ok((tasks, _)) => Ok(tasks),
+            Err(EfficacyError::SerdeJsonError(e)) => {
+                Err(corrupt_file_error(&file_path, &tasks_string, e))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save_archive(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        let file_path = self.archive_file_path(context_name);
+        std::fs::create_dir_all(self.data_dir.join("archive"))?;
+
+        let tasks_serialized = migrations::serialize_tasks(tasks).unwrap();
+        write_atomically(&file_path, tasks_serialized.as_bytes())
+    }
+
+    fn create_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        if RESERVED_FILE_STEMS.contains(&context_name) {
+            return Err(EfficacyError::BadContextNameError);
+        }
+
+        File::create(self.task_file_path(context_name))?;
+
+        Ok(())
+    }
+
+    fn delete_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        std::fs::remove_file(self.task_file_path(context_name))?;
+        self.fingerprints.remove(context_name);
+
+        let archive_file_path = self.archive_file_path(context_name);
+        if archive_file_path.exists() {
+            std::fs::remove_file(archive_file_path)?;
+        }
+
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> EfficacyResult<Journal> {
+        let journal_file_path = self.journal_file_path();
+        if !journal_file_path.exists() {
+            return Ok(Journal::default());
+        }
+
+        let journal_string = std::fs::read_to_string(&journal_file_path)?;
+
+        match serde_json::from_str(&journal_string[..]) {
+            Ok(j) => Ok(j),
+            Err(e) => Err(corrupt_file_error(&journal_file_path, &journal_string, e)),
+        }
+    }
+
+    fn save_journal(&mut self, journal: &Journal) -> EfficacyResult<()> {
+        let journal_serialized = serde_json::to_string(journal)?;
+
+        write_atomically(&self.journal_file_path(), journal_serialized.as_bytes())
+    }
+
+    fn load_trash(&mut self) -> EfficacyResult<Trash> {
+        let trash_file_path = self.trash_file_path();
+        if !trash_file_path.exists() {
+            return Ok(Trash::default());
+        }
+
+        let trash_string = std::fs::read_to_string(&trash_file_path)?;
+
+        match serde_json::from_str(&trash_string[..]) {
+            Ok(t) => Ok(t),
+            Err(e) => Err(corrupt_file_error(&trash_file_path, &trash_string, e)),
+        }
+    }
+
+    fn save_trash(&mut self, trash: &Trash) -> EfficacyResult<()> {
+        std::fs::create_dir_all(self.data_dir.join("trash"))?;
+        let trash_serialized = serde_json::to_string(trash)?;
+
+        write_atomically(&self.trash_file_path(), trash_serialized.as_bytes())
+    }
+}
+
+/// Every file in the data directory holding the tasks of a context.
+fn task_file_paths(data_dir: &Path) -> EfficacyResult<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for entry in std::fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        if !RESERVED_FILE_STEMS.contains(&stem) {
+            paths.push(path);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Copies a file that failed to parse aside, so it can't be lost by a later save, and builds
+/// the error describing where it went wrong.
+fn corrupt_file_error(path: &Path, contents: &str, error: serde_json::Error) -> EfficacyError {
+    let quarantine_path = with_added_extension(
+        path,
+        &format!("corrupt-{:x}", fingerprint(contents.as_bytes())),
+    );
+
+    EfficacyError::CorruptFileError {
+        path: path.to_path_buf(),
+        line: error.line(),
+        column: error.column(),
+        quarantine_path: match std::fs::write(&quarantine_path, contents) {
+            Ok(_) => Some(quarantine_path),
+            Err(_) => None,
+        },
+    }
+}
+
+fn restore_if_unreadable<F>(path: &Path, check: F) -> EfficacyResult<bool>
+where
+    F: Fn(&str) -> EfficacyResult<()>,
+{
+    let contents = std::fs::read_to_string(path)?;
+    if check(&contents).is_ok() {
+        return Ok(false);
+    }
+
+    let backup_path = with_added_extension(path, "bak");
+    let backup_contents = match std::fs::read_to_string(&backup_path) {
+        Ok(c) => c,
+        Err(_) => return Err(EfficacyError::NoBackupError(path.to_path_buf())),
+    };
+    if check(&backup_contents).is_err() {
+        return Err(EfficacyError::NoBackupError(path.to_path_buf()));
+    }
+
+    let quarantine_path = with_added_extension(
+        path,
+        &format!("corrupt-{:x}", fingerprint(contents.as_bytes())),
+    );
+    std::fs::write(quarantine_path, &contents)?;
+    std::fs::copy(&backup_path, path)?;
+
+    Ok(true)
+}
+
+/// Replaces the file at `path` without ever leaving it half written. The contents go to a
+/// temporary file that is synced to disk and then renamed over the original, and the previous
+/// version is kept alongside it with a `.bak` extension.
+fn write_atomically(path: &Path, contents: &[u8]) -> EfficacyResult<()> {
+    let temp_path = with_added_extension(path, "tmp");
+    let backup_path = with_added_extension(path, "bak");
+
+    let mut temp_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp_path)?;
+    temp_file.write_all(contents)?;
+    temp_file.sync_all()?;
+
+    if path.exists() && std::fs::metadata(path)?.len() > 0 {
+        std::fs::copy(path, &backup_path)?;
+    }
+
+    std::fs::rename(&temp_path, path)?;
+
+    // Make sure the rename itself survives a crash
+    #[cfg(unix)]
+    {
+        if let Some(parent) = path.parent() {
+            File::open(parent)?.sync_all()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn fingerprint(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.as_os_str().to_owned();
+    file_name.push(".");
+    file_name.push(extension);
+    PathBuf::from(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn atomic_writes_keep_a_backup() {
+        let path = std::env::temp_dir().join("efficacy_atomic_write_test.json");
+        let backup_path = super::with_added_extension(&path, "bak");
+
+        super::write_atomically(&path, b"[1]").unwrap();
+        super::write_atomically(&path, b"[2]").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), "[1]");
+        assert!(!super::with_added_extension(&path, "tmp").exists());
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(backup_path).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use super::super::{
+    errors::EfficacyError, journal::Journal, objects, trash::Trash, EfficacyResult,
+};
+use super::Storage;
+
+/// Keeps everything in memory and forgets it when dropped. Meant for tests.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    context: objects::Context,
+    tasks: HashMap<String, Vec<objects::Task>>,
+    archives: HashMap<String, Vec<objects::Task>>,
+    journal: Journal,
+    trash: Trash,
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        let mut tasks = HashMap::new();
+        tasks.insert(String::from("default"), Vec::new());
+
+        MemoryStorage {
+            context: objects::Context {
+                context_name: String::from("default"),
+                next_ids: HashMap::new(),
+            },
+            tasks,
+            archives: HashMap::new(),
+            journal: Journal::default(),
+            trash: Trash::default(),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn context_names(&self) -> EfficacyResult<Vec<String>> {
+        Ok(self.tasks.keys().cloned().collect())
+    }
+
+    fn load_context(&mut self) -> EfficacyResult<objects::Context> {
+        Ok(self.context.clone())
+    }
+
+    fn save_context(&mut self, context: &objects::Context) -> EfficacyResult<()> {
+        self.context = context.clone();
+        Ok(())
+    }
+
+    fn load_tasks(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        match self.tasks.get(context_name) {
+            Some(tasks) => Ok(tasks.clone()),
+            None => Err(EfficacyError::MalformedContextError),
+        }
+    }
+
+    fn save_tasks(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        match self.tasks.get_mut(context_name) {
+            Some(stored) => {
+                *stored = tasks.to_vec();
+                Ok(())
+            }
+            None => Err(EfficacyError::MalformedContextError),
+        }
+    }
+
+    fn load_archive(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        Ok(self.archives.get(context_name).cloned().unwrap_or_default())
+    }
+
+    fn save_archive(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        self.archives
+            .insert(String::from(context_name), tasks.to_vec());
+        Ok(())
+    }
+
+    fn create_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        self.tasks.insert(String::from(context_name), Vec::new());
+        Ok(())
+    }
+
+    fn delete_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        self.tasks.remove(context_name);
+        self.archives.remove(context_name);
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> EfficacyResult<Journal> {
+        Ok(self.journal.clone())
+    }
+
+    fn save_journal(&mut self, journal: &Journal) -> EfficacyResult<()> {
+        self.journal = journal.clone();
+        Ok(())
+    }
+
+    fn load_trash(&mut self) -> EfficacyResult<Trash> {
+        Ok(self.trash.clone())
+    }
+
+    fn save_trash(&mut self, trash: &Trash) -> EfficacyResult<()> {
+        self.trash = trash.clone();
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+mod json;
+mod memory;
+mod sqlite;
+
+use std::path::PathBuf;
+
+pub use json::JsonStorage;
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+use super::{journal::Journal, objects, settings::Settings, trash::Trash, EfficacyResult};
+
+/// Backends that can be chosen with the `storage` setting.
+pub const STORAGE_BACKENDS: [&str; 2] = ["json", "sqlite"];
+
+/// Somewhere tasks, contexts, the undo journal and the trash are kept between runs. Every backend
+/// always has a "default" context.
+pub trait Storage: std::fmt::Debug {
+    /// Names of every context, in no particular order.
+    fn context_names(&self) -> EfficacyResult<Vec<String>>;
+
+    /// The context record, holding which context is current and the ID counters.
+    fn load_context(&mut self) -> EfficacyResult<objects::Context>;
+
+    fn save_context(&mut self, context: &objects::Context) -> EfficacyResult<()>;
+
+    fn load_tasks(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>>;
+
+    /// Replaces every task of a context. Fails with a `FileChangedError` if the tasks were
+    /// changed by something else since they were loaded.
+    fn save_tasks(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()>;
+
+    /// Done tasks that were cleared out of a context, which is empty until the first clean.
+    fn load_archive(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>>;
+
+    /// Replaces every archived task of a context.
+    fn save_archive(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()>;
+
+    fn create_context(&mut self, context_name: &str) -> EfficacyResult<()>;
+
+    /// Removes a context along with all of its tasks and its archive.
+    fn delete_context(&mut self, context_name: &str) -> EfficacyResult<()>;
+
+    fn load_journal(&mut self) -> EfficacyResult<Journal>;
+
+    fn save_journal(&mut self, journal: &Journal) -> EfficacyResult<()>;
+
+    fn load_trash(&mut self) -> EfficacyResult<Trash>;
+
+    fn save_trash(&mut self, trash: &Trash) -> EfficacyResult<()>;
+
+    /// Holds back the writes that follow until `commit`, so the writes of one operation are
+    /// kept together or not at all. Backends that can't do this keep each write as it is made.
+    fn begin(&mut self) -> EfficacyResult<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> EfficacyResult<()> {
+        Ok(())
+    }
+
+    /// Drops every write made since `begin`.
+    fn rollback(&mut self) -> EfficacyResult<()> {
+        Ok(())
+    }
+}
+
+/// Opens the backend picked in the settings, creating whatever it needs in the data directory.
+/// A `read_only` backend upgrades old files in memory only, leaving them for the next command
+/// that holds the lock to rewrite.
+///
+/// The first time SQLite is used, the tasks, archives, journal and trash kept by the JSON
+/// backend are copied into the new database. The JSON files are left as they are.
+pub fn open(settings: &Settings, read_only: bool) -> EfficacyResult<Box<dyn Storage>> {
+    let data_dir = PathBuf::from(&settings.data_file_path);
+
+    match &settings.storage[..] {
+        "sqlite" => {
+            let mut storage =
+                SqliteStorage::open(&data_dir.join("efficacy.db"), settings.lock_timeout)?;
+            if storage.is_unused()? && JsonStorage::exists(&data_dir) {
+                storage.import_from(&mut JsonStorage::open(&data_dir, true)?)?;
+            }
+            Ok(Box::new(storage))
+        }
+        _ => Ok(Box::new(JsonStorage::open(&data_dir, read_only)?)),
+    }
+}
+
+/// Restores unreadable data from backups, returning the files that were restored. Only the
+/// JSON backend keeps backups, SQLite databases can't be left half written.
+pub fn recover(settings: &Settings) -> EfficacyResult<Vec<PathBuf>> {
+    match &settings.storage[..] {
+        "sqlite" => Ok(Vec::new()),
+        _ => JsonStorage::recover(&PathBuf::from(&settings.data_file_path)),
+    }
+}
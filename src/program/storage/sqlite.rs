@@ -0,0 +1,563 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::super::{
+    errors::EfficacyError, journal::Journal, objects, state, trash::Trash, EfficacyResult,
+};
+use super::Storage;
+
+/// Version of the database schema written by this build, kept in `PRAGMA user_version`.
+const SCHEMA_VERSION: u32 = 2;
+
+/// The schema of new databases, at `SCHEMA_VERSION`.
+const SCHEMA: &str = "
+    CREATE TABLE contexts (
+        name TEXT PRIMARY KEY,
+        revision INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE tasks (
+        context TEXT NOT NULL REFERENCES contexts (name) ON DELETE CASCADE,
+        id INTEGER NOT NULL,
+        state TEXT NOT NULL,
+        category TEXT,
+        due TEXT,
+        data TEXT NOT NULL,
+        PRIMARY KEY (context, id)
+    );
+    CREATE INDEX tasks_by_state ON tasks (context, state);
+    CREATE INDEX tasks_by_category ON tasks (context, category);
+    CREATE INDEX tasks_by_due ON tasks (context, due);
+    CREATE TABLE documents (
+        name TEXT PRIMARY KEY,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE archive (
+        context TEXT NOT NULL REFERENCES contexts (name) ON DELETE CASCADE,
+        id INTEGER NOT NULL,
+        completed TEXT,
+        data TEXT NOT NULL,
+        PRIMARY KEY (context, id)
+    );
+    INSERT INTO contexts (name) VALUES ('default');
+";
+
+/// Upgrades of existing databases, in order. The statements at index `i` take a database from
+/// version `i + 1` to version `i + 2`.
+const UPGRADES: [&str; 1] = ["
+    CREATE TABLE archive (
+        context TEXT NOT NULL REFERENCES contexts (name) ON DELETE CASCADE,
+        id INTEGER NOT NULL,
+        completed TEXT,
+        data TEXT NOT NULL,
+        PRIMARY KEY (context, id)
+    );
+"];
+
+/// Every context in a single SQLite database. Each task is stored as JSON, with the columns
+/// that tasks are looked up by copied out next to it so they can be indexed. Saves happen in a
+/// transaction, so a crash never leaves a context half written, and `begin` makes every save
+/// until `commit` part of one.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: Connection,
+    /// Revisions of contexts as they were last loaded or saved, by context name.
+    revisions: HashMap<String, i64>,
+    /// How many `begin`s are still to be committed.
+    open_transactions: usize,
+    /// The revisions from before the outermost `begin`, to go back to on a rollback.
+    revisions_before: HashMap<String, i64>,
+}
+
+impl SqliteStorage {
+    /// Opens the database, creating it if needed. Waits for up to `busy_timeout` seconds when
+    /// another connection is writing.
+    pub fn open(path: &Path, busy_timeout: u64) -> EfficacyResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(Duration::from_secs(busy_timeout))?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+        let version: u32 = connection.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(EfficacyError::UnsupportedVersionError(u64::from(version)));
+        }
+        if version == 0 {
+            connection.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                SCHEMA, SCHEMA_VERSION
+            ))?;
+        } else {
+            for (index, upgrade) in UPGRADES.iter().enumerate().skip(version as usize - 1) {
+                connection.execute_batch(&format!(
+                    "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                    upgrade,
+                    index + 2
+                ))?;
+            }
+        }
+
+        Ok(SqliteStorage {
+            path: path.to_path_buf(),
+            connection,
+            revisions: HashMap::new(),
+            open_transactions: 0,
+            revisions_before: HashMap::new(),
+        })
+    }
+
+    /// Copies everything kept by another backend into a database that nothing has been saved
+    /// in yet, so switching the `storage` setting keeps the tasks. The copy is made in one
+    /// transaction. Returns whether anything was copied.
+    pub fn import_from(&mut self, other: &mut dyn Storage) -> EfficacyResult<bool> {
+        self.begin()?;
+
+        match self.copy_if_unused(other) {
+            Ok(copied) => {
+                self.commit()?;
+                Ok(copied)
+            }
+            Err(e) => {
+                self.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Whether nothing has been saved in the database yet.
+    pub fn is_unused(&self) -> EfficacyResult<bool> {
+        // Every save writes the context record
+        Ok(self.load_document("context")?.is_none())
+    }
+
+    fn copy_if_unused(&mut self, other: &mut dyn Storage) -> EfficacyResult<bool> {
+        // Another command may have got here first
+        if !self.is_unused()? {
+            return Ok(false);
+        }
+
+        let mut context = other.load_context()?;
+        for name in other.context_names()? {
+            // Files from before stored IDs only get them when loaded with the lock
+            let mut tasks = other.load_tasks(&name)?;
+            state::assign_missing_ids_in(&mut context.next_ids, &name, &mut tasks);
+
+            self.create_context(&name)?;
+            self.save_tasks(&name, &tasks)?;
+            self.save_archive(&name, &other.load_archive(&name)?)?;
+        }
+        self.save_journal(&other.load_journal()?)?;
+        self.save_trash(&other.load_trash()?)?;
+        self.save_context(&context)?;
+
+        Ok(true)
+    }
+
+    fn load_document(&self, name: &str) -> EfficacyResult<Option<String>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT data FROM documents WHERE name = ?1",
+                params![name],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    fn save_document(&self, name: &str, data: &str) -> EfficacyResult<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO documents (name, data) VALUES (?1, ?2)",
+            params![name, data],
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn context_names(&self) -> EfficacyResult<Vec<String>> {
+        let mut statement = self.connection.prepare("SELECT name FROM contexts")?;
+        let names = statement
+            .query_map(params![], |r| r.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(names)
+    }
+
+    fn load_context(&mut self) -> EfficacyResult<objects::Context> {
+        match self.load_document("context")? {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(objects::Context {
+                context_name: String::from("default"),
+                next_ids: HashMap::new(),
+            }),
+        }
+    }
+
+    fn save_context(&mut self, context: &objects::Context) -> EfficacyResult<()> {
+        self.save_document("context", &serde_json::to_string(context)?)
+    }
+
+    fn load_tasks(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        let transaction = self.connection.savepoint()?;
+
+        let revision: i64 = match transaction
+            .query_row(
+                "SELECT revision FROM contexts WHERE name = ?1",
+                params![context_name],
+                |r| r.get(0),
+            )
+            .optional()?
+        {
+            Some(r) => r,
+            None => return Err(EfficacyError::MalformedContextError),
+        };
+
+        let mut tasks = Vec::new();
+        {
+            let mut statement =
+                transaction.prepare("SELECT data FROM tasks WHERE context = ?1 ORDER BY id")?;
+            let rows = statement.query_map(params![context_name], |r| r.get::<_, String>(0))?;
+            for data in rows {
+                tasks.push(serde_json::from_str(&data?)?);
+            }
+        }
+        transaction.commit()?;
+
+        self.revisions.insert(String::from(context_name), revision);
+
+        Ok(tasks)
+    }
+
+    fn save_tasks(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        let transaction = self.connection.savepoint()?;
+
+        let revision: i64 = match transaction
+            .query_row(
+                "SELECT revision FROM contexts WHERE name = ?1",
+                params![context_name],
+                |r| r.get(0),
+            )
+            .optional()?
+        {
+            Some(r) => r,
+            None => return Err(EfficacyError::MalformedContextError),
+        };
+
+        // Refuse to clobber changes made by someone else since we loaded
+        if let Some(loaded) = self.revisions.get(context_name) {
+            if revision != *loaded {
+                return Err(EfficacyError::FileChangedError(self.path.clone()));
+            }
+        }
+
+        transaction.execute(
+            "DELETE FROM tasks WHERE context = ?1",
+            params![context_name],
+        )?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO tasks (context, id, state, category, due, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?;
+            for task in tasks {
+                statement.execute(params![
+                    context_name,
+                    task.id as i64,
+                    format!("{:?}", task.state),
+                    task.category,
+                    task.due.map(|d| d.to_rfc3339()),
+                    serde_json::to_string(task)?,
+                ])?;
+            }
+        }
+        transaction.execute(
+            "UPDATE contexts SET revision = ?1 WHERE name = ?2",
+            params![revision + 1, context_name],
+        )?;
+        transaction.commit()?;
+
+        self.revisions
+            .insert(String::from(context_name), revision + 1);
+
+        Ok(())
+    }
+
+    fn load_archive(&mut self, context_name: &str) -> EfficacyResult<Vec<objects::Task>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT data FROM archive WHERE context = ?1 ORDER BY id")?;
+        let rows = statement.query_map(params![context_name], |r| r.get::<_, String>(0))?;
+
+        let mut tasks = Vec::new();
+        for data in rows {
+            tasks.push(serde_json::from_str(&data?)?);
+        }
+
+        Ok(tasks)
+    }
+
+    fn save_archive(&mut self, context_name: &str, tasks: &[objects::Task]) -> EfficacyResult<()> {
+        let transaction = self.connection.savepoint()?;
+
+        transaction.execute(
+            "DELETE FROM archive WHERE context = ?1",
+            params![context_name],
+        )?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO archive (context, id, completed, data) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for task in tasks {
+                statement.execute(params![
+                    context_name,
+                    task.id as i64,
+                    task.completed.map(|c| c.to_rfc3339()),
+                    serde_json::to_string(task)?,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+
+        Ok(())
+    }
+
+    fn create_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        self.connection.execute(
+            "INSERT OR IGNORE INTO contexts (name) VALUES (?1)",
+            params![context_name],
+        )?;
+
+        Ok(())
+    }
+
+    fn delete_context(&mut self, context_name: &str) -> EfficacyResult<()> {
+        self.connection.execute(
+            "DELETE FROM contexts WHERE name = ?1",
+            params![context_name],
+        )?;
+        self.revisions.remove(context_name);
+
+        Ok(())
+    }
+
+    fn load_journal(&mut self) -> EfficacyResult<Journal> {
+        match self.load_document("journal")? {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(Journal::default()),
+        }
+    }
+
+    fn save_journal(&mut self, journal: &Journal) -> EfficacyResult<()> {
+        self.save_document("journal", &serde_json::to_string(journal)?)
+    }
+
+    fn load_trash(&mut self) -> EfficacyResult<Trash> {
+        match self.load_document("trash")? {
+            Some(data) => Ok(serde_json::from_str(&data)?),
+            None => Ok(Trash::default()),
+        }
+    }
+
+    fn save_trash(&mut self, trash: &Trash) -> EfficacyResult<()> {
+        self.save_document("trash", &serde_json::to_string(trash)?)
+    }
+
+    fn begin(&mut self) -> EfficacyResult<()> {
+        if self.open_transactions == 0 {
+            // Taking the write lock up front keeps revisions from changing underneath
+            self.connection.execute_batch("BEGIN IMMEDIATE")?;
+            self.revisions_before = self.revisions.clone();
+        }
+        self.open_transactions += 1;
+
+        Ok(())
+    }
+
+    fn commit(&mut self) -> EfficacyResult<()> {
+        match self.open_transactions {
+            0 => Ok(()),
+            1 => {
+                self.connection.execute_batch("COMMIT")?;
+                self.open_transactions = 0;
+                Ok(())
+            }
+            _ => {
+                self.open_transactions -= 1;
+                Ok(())
+            }
+        }
+    }
+
+    fn rollback(&mut self) -> EfficacyResult<()> {
+        if self.open_transactions == 0 {
+            return Ok(());
+        }
+
+        // Nothing of the operation can be kept once part of it failed
+        self.open_transactions = 0;
+        self.revisions = std::mem::take(&mut self.revisions_before);
+        self.connection.execute_batch("ROLLBACK")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::objects::Task;
+    use super::*;
+
+    #[test]
+    fn contexts_round_trip() {
+        let path = std::env::temp_dir().join("efficacy_sqlite_test.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut task = Task::new(String::from("Workout"));
+        task.id = 0;
+        task.category = Some(String::from("Personal"));
+
+        {
+            let mut storage = SqliteStorage::open(&path, 1).unwrap();
+            storage.create_context("work").unwrap();
+            storage.save_tasks("work", &[task.clone()]).unwrap();
+        }
+
+        let mut storage = SqliteStorage::open(&path, 1).unwrap();
+        let mut names = storage.context_names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["default", "work"]);
+        assert_eq!(storage.load_tasks("work").unwrap(), vec![task.clone()]);
+        assert!(storage.load_tasks("default").unwrap().is_empty());
+
+        // A second connection saving in between is noticed
+        SqliteStorage::open(&path, 1)
+            .unwrap()
+            .save_tasks("work", &[])
+            .unwrap();
+        match storage.save_tasks("work", &[task.clone()]) {
+            Err(EfficacyError::FileChangedError(_)) => (),
+            other => panic!("Expected a FileChangedError, got {:?}", other),
+        }
+
+        storage.save_archive("work", &[task.clone()]).unwrap();
+        assert_eq!(storage.load_archive("work").unwrap(), vec![task]);
+
+        storage.delete_context("work").unwrap();
+        assert_eq!(storage.context_names().unwrap(), vec!["default"]);
+        assert!(storage.load_archive("work").unwrap().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn operations_are_saved_together() {
+        let path = std::env::temp_dir().join("efficacy_sqlite_transaction_test.db");
+        let _ = std::fs::remove_file(&path);
+
+        let mut task = Task::new(String::from("Workout"));
+        task.id = 0;
+
+        let mut storage = SqliteStorage::open(&path, 1).unwrap();
+        storage.load_tasks("default").unwrap();
+        storage.begin().unwrap();
+        storage.save_tasks("default", &[task.clone()]).unwrap();
+        storage.save_trash(&Trash::default()).unwrap();
+        storage.rollback().unwrap();
+        assert!(storage.load_tasks("default").unwrap().is_empty());
+        assert!(storage.load_document("trash").unwrap().is_none());
+
+        // Nested operations are committed with the outermost one
+        storage.begin().unwrap();
+        storage.begin().unwrap();
+        storage.save_tasks("default", &[task.clone()]).unwrap();
+        storage.commit().unwrap();
+        assert!(SqliteStorage::open(&path, 1)
+            .unwrap()
+            .load_tasks("default")
+            .unwrap()
+            .is_empty());
+        storage.commit().unwrap();
+        assert_eq!(
+            SqliteStorage::open(&path, 1)
+                .unwrap()
+                .load_tasks("default")
+                .unwrap(),
+            vec![task]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn json_data_is_copied_on_first_use() {
+        use super::super::super::objects::UNASSIGNED_ID;
+        use super::super::super::test_support::test_settings;
+        use super::super::{open, JsonStorage};
+
+        let data_dir = std::env::temp_dir().join("efficacy_sqlite_migration_test");
+        let _ = std::fs::remove_dir_all(&data_dir);
+
+        let mut report = Task::new(String::from("Write report"));
+        report.id = 0;
+        let mut legacy = Task::new(String::from("Answer email"));
+        legacy.id = UNASSIGNED_ID;
+        {
+            let mut json = JsonStorage::open(&data_dir, false).unwrap();
+            json.create_context("work").unwrap();
+            json.save_tasks("work", &[report.clone(), legacy.clone()])
+                .unwrap();
+            json.save_archive("work", &[report.clone()]).unwrap();
+        }
+
+        let mut settings = test_settings(&data_dir);
+        settings.storage = String::from("sqlite");
+        let mut storage = open(&settings, true).unwrap();
+        let mut names = storage.context_names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["default", "work"]);
+        let tasks = storage.load_tasks("work").unwrap();
+        assert_eq!(
+            tasks.iter().map(|t| t.id).collect::<Vec<usize>>(),
+            vec![0, 1]
+        );
+        assert_eq!(storage.load_archive("work").unwrap(), vec![report]);
+
+        // Only the first use copies anything
+        storage.save_tasks("work", &[]).unwrap();
+        let context = storage.load_context().unwrap();
+        storage.save_context(&context).unwrap();
+        drop(storage);
+        let mut storage = open(&settings, true).unwrap();
+        assert!(storage.load_tasks("work").unwrap().is_empty());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn older_databases_are_upgraded() {
+        let path = std::env::temp_dir().join("efficacy_sqlite_upgrade_test.db");
+        let _ = std::fs::remove_file(&path);
+
+        SqliteStorage::open(&path, 1)
+            .unwrap()
+            .connection
+            .execute_batch("DROP TABLE archive; PRAGMA user_version = 1;")
+            .unwrap();
+
+        let mut storage = SqliteStorage::open(&path, 1).unwrap();
+        assert!(storage.load_archive("default").unwrap().is_empty());
+        let version: u32 = storage
+            .connection
+            .pragma_query_value(None, "user_version", |r| r.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+use chrono::NaiveTime;
+use std::path::Path;
+
+use super::settings::Settings;
+use super::storage::MemoryStorage;
+use super::Efficacy;
+
+/// Settings for tests, keeping data in `data_dir` with the JSON backend. Backends given to
+/// `with_storage` ignore the directory, so an empty path does for those.
+pub fn test_settings(data_dir: &Path) -> Settings {
+    Settings {
+        data_file_path: String::from(data_dir.to_str().unwrap()),
+        config_file_path: String::new(),
+        task_format: String::from("%b %d %i"),
+        lock_timeout: 1,
+        storage: String::from("json"),
+        default_due_time: NaiveTime::from_hms(8, 0, 0),
+        sort: String::from("priority"),
+        group_by: String::from("category"),
+        output: String::from("text"),
+        trash_days: 30,
+    }
+}
+
+/// An `Efficacy` on the test settings that keeps everything in memory.
+pub fn memory_efficacy() -> Efficacy<'static> {
+    memory_efficacy_with(test_settings(Path::new("")))
+}
+
+/// Like `memory_efficacy`, for tests that need settings of their own. The settings are leaked
+/// so the `Efficacy` can borrow them for the rest of the test.
+pub fn memory_efficacy_with(config: Settings) -> Efficacy<'static> {
+    let config = Box::leak(Box::new(config));
+    Efficacy::with_storage(config, Box::new(MemoryStorage::default())).unwrap()
+}
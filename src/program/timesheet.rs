@@ -0,0 +1,134 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+use super::objects::Task;
+
+/// Time spent on one task on one day, in local time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TimeEntry {
+    pub date: NaiveDate,
+    pub task_id: usize,
+    pub description: String,
+    pub category: Option<String>,
+    pub duration: Duration,
+}
+
+/// Splits the work recorded on `tasks` since `since` into days, giving one entry per task and
+/// day, ordered by day and then as the tasks are. A clock that is still running counts up to
+/// `now`.
+pub fn entries(tasks: &[Task], since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Vec<TimeEntry> {
+    let mut totals: BTreeMap<(NaiveDate, usize), Duration> = BTreeMap::new();
+
+    for (index, task) in tasks.iter().enumerate() {
+        for interval in &task.time_log {
+            let mut start = match since {
+                Some(since) if since > interval.start => since,
+                _ => interval.start,
+            };
+            let end = interval.end.unwrap_or(now);
+
+            // Work past midnight counts towards the next day
+            while start < end {
+                let day = start.with_timezone(&Local).date();
+                let midnight = day
+                    .succ()
+                    .and_hms_opt(0, 0, 0)
+                    .map_or(end, |m| m.with_timezone(&Utc));
+                let until = std::cmp::min(end, midnight);
+
+                let total = totals
+                    .entry((day.naive_local(), index))
+                    .or_insert_with(Duration::zero);
+                *total = *total + (until - start);
+                start = until;
+            }
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|((date, index), duration)| TimeEntry {
+            date,
+            task_id: tasks[index].id,
+            description: tasks[index].description.clone(),
+            category: tasks[index].category.clone(),
+            duration,
+        })
+        .collect()
+}
+
+/// Adds up the entries sharing a key, in key order.
+pub fn totals_by<K, F>(entries: &[TimeEntry], key: F) -> Vec<(K, Duration)>
+where
+    K: Ord,
+    F: Fn(&TimeEntry) -> K,
+{
+    let mut totals: BTreeMap<K, Duration> = BTreeMap::new();
+
+    for entry in entries {
+        let total = totals.entry(key(entry)).or_insert_with(Duration::zero);
+        *total = *total + entry.duration;
+    }
+
+    totals.into_iter().collect()
+}
+
+/// Hours as a decimal, as used in CSV and JSON output.
+pub fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::objects::WorkInterval;
+    use chrono::{Datelike, TimeZone};
+
+    #[test]
+    fn work_is_split_into_days() {
+        let at = |day, hour| {
+            Local
+                .ymd(2026, 10, day)
+                .and_hms(hour, 0, 0)
+                .with_timezone(&Utc)
+        };
+
+        let mut report = Task::new(String::from("Write report"));
+        report.id = 3;
+        report.category = Some(String::from("Work"));
+        report.time_log = vec![
+            WorkInterval {
+                start: at(11, 9),
+                end: Some(at(11, 11)),
+            },
+            WorkInterval {
+                start: at(12, 22),
+                end: Some(at(13, 1)),
+            },
+        ];
+        let mut email = Task::new(String::from("Answer email"));
+        email.id = 5;
+        email.time_log = vec![WorkInterval {
+            start: at(13, 9),
+            end: None,
+        }];
+
+        let entries = entries(&[report, email], Some(at(11, 10)), at(13, 10));
+        let summary: Vec<(u32, usize, i64)> = entries
+            .iter()
+            .map(|e| (e.date.day(), e.task_id, e.duration.num_minutes()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(11, 3, 60), (12, 3, 120), (13, 3, 60), (13, 5, 60)]
+        );
+
+        let by_category = totals_by(&entries, |e| e.category.clone());
+        assert_eq!(by_category[0], (None, Duration::hours(1)));
+        assert_eq!(
+            by_category[1],
+            (Some(String::from("Work")), Duration::hours(4))
+        );
+        assert_eq!(hours(Duration::minutes(90)), 1.5);
+    }
+}
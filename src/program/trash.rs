@@ -0,0 +1,164 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::objects::Task;
+
+/// What was deleted, with everything needed to bring it back.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Trashed {
+    /// A task, along with the subtasks deleted with it.
+    Task {
+        tasks: Vec<Task>,
+    },
+    Category {
+        name: String,
+        tasks: Vec<Task>,
+    },
+    /// A whole context, along with its archive and its next task ID.
+    Context {
+        tasks: Vec<Task>,
+        archive: Vec<Task>,
+        next_id: Option<usize>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    /// What the entry is restored by. Never reused, like task IDs.
+    pub id: usize,
+    /// The context the item was deleted from, or the deleted context itself.
+    pub context: String,
+    pub deleted: DateTime<Utc>,
+    pub item: Trashed,
+}
+
+impl TrashEntry {
+    /// Name of what was deleted: a task's description, or the name of a category or context.
+    pub fn name(&self) -> &str {
+        match &self.item {
+            Trashed::Task { tasks } => tasks.first().map_or("", |t| &t.description[..]),
+            Trashed::Category { name, .. } => name,
+            Trashed::Context { .. } => &self.context,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.item {
+            Trashed::Task { .. } => "task",
+            Trashed::Category { .. } => "category",
+            Trashed::Context { .. } => "context",
+        }
+    }
+
+    /// How many tasks restoring the entry brings back, not counting archived ones.
+    pub fn task_count(&self) -> usize {
+        match &self.item {
+            Trashed::Task { tasks }
+            | Trashed::Category { tasks, .. }
+            | Trashed::Context { tasks, .. } => tasks.len(),
+        }
+    }
+}
+
+/// Deleted tasks, categories and contexts of every context, oldest first.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Trash {
+    #[serde(default)]
+    pub next_id: usize,
+    #[serde(default)]
+    pub entries: Vec<TrashEntry>,
+}
+
+impl Trash {
+    /// Keeps a deleted item, returning the ID it can be restored by.
+    pub fn add(&mut self, context: &str, item: Trashed) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.entries.push(TrashEntry {
+            id,
+            context: String::from(context),
+            deleted: Utc::now(),
+            item,
+        });
+
+        id
+    }
+
+    /// Takes an entry out of the trash.
+    pub fn take(&mut self, id: usize) -> Option<TrashEntry> {
+        let index = self.entries.iter().position(|e| e.id == id)?;
+        Some(self.entries.remove(index))
+    }
+
+    /// Takes out the entries in `remove` and puts back the ones in `restore`, for undoing and
+    /// redoing changes that went through the trash.
+    pub fn swap_entries(&mut self, remove: &[TrashEntry], restore: &[TrashEntry]) {
+        self.entries.retain(|e| {
+            !remove.iter().any(|r| r.id == e.id) && !restore.iter().any(|r| r.id == e.id)
+        });
+        self.entries.extend(restore.iter().cloned());
+        self.entries.sort_by_key(|e| e.id);
+    }
+
+    /// Drops entries deleted before `cutoff`, returning their IDs.
+    pub fn purge_before(&mut self, cutoff: DateTime<Utc>) -> Vec<usize> {
+        let (purged, kept) = self.entries.drain(..).partition(|e| e.deleted < cutoff);
+        self.entries = kept;
+        purged.into_iter().map(|e| e.id).collect()
+    }
+
+    /// Drops every entry, returning their IDs.
+    pub fn empty(&mut self) -> Vec<usize> {
+        self.entries.drain(..).map(|e| e.id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn entries_are_taken_and_purged() {
+        let mut trash = Trash::default();
+        let task = trash.add(
+            "default",
+            Trashed::Task {
+                tasks: vec![Task::new(String::from("Write report"))],
+            },
+        );
+        let category = trash.add(
+            "default",
+            Trashed::Category {
+                name: String::from("Work"),
+                tasks: Vec::new(),
+            },
+        );
+        assert_eq!((task, category), (0, 1));
+        assert_eq!(trash.entries[0].name(), "Write report");
+
+        trash.entries[0].deleted = Utc::now() - Duration::days(40);
+        assert_eq!(
+            trash.purge_before(Utc::now() - Duration::days(30)),
+            vec![task]
+        );
+
+        assert!(trash.take(task).is_none());
+        assert_eq!(trash.take(category).unwrap().kind(), "category");
+        assert!(trash.entries.is_empty());
+
+        // IDs aren't handed out twice
+        let next = trash.add(
+            "work",
+            Trashed::Context {
+                tasks: Vec::new(),
+                archive: Vec::new(),
+                next_id: None,
+            },
+        );
+        assert_eq!(next, 2);
+        assert_eq!(trash.entries[0].name(), "work");
+    }
+}